@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use crate::{constraints::{Boundary, ConstraintSolver, Edges, Grid, SelectionStrategy}, error::Error, tile_set::TileType};
+
+//an endless map made of fixed size chunks that get generated the first time they're asked for.
+//a chunk owns its top row and left column, the seams. seams are solved on their own from the world seed and
+//where they are, so the chunks on either side see the same seam whichever of them is generated first, and every
+//chunk comes out the same in any order. the inside of a chunk is then solved with the seams all around it pinned.
+//chunks that were already generated never constrain a new one, only the seams it shares with them do. the corners
+//and seams come from separate solves that nothing checks against each other, they only fit because each seam has
+//free cells between the corners at either end, which is why chunks have to be at least 4 x 4
+pub struct ChunkedWorld{
+    //tiles, constraints and weights every chunk is solved with
+    template:ConstraintSolver,
+    chunk_width:usize,
+    chunk_height:usize,
+    world_seed:u64,
+    chunks:HashMap<(i64, i64), Grid>,
+}
+
+//splitmix64, spreads nearby inputs far apart
+fn mix(value:u64)->u64{
+    let mut z = value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z>>30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z>>27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z>>31)
+}
+
+//the pieces of the world that get solved on their own, each one gets its own seed
+#[derive(Clone, Copy)]
+enum Part{Corner=1, Top, Left}
+
+//the tiles around the point where four chunks meet, the corner itself and the first cell of each seam leaving it
+#[derive(Clone, Copy)]
+struct Corner{
+    tile:TileType,
+    up:TileType,
+    down:TileType,
+    left:TileType,
+    right:TileType,
+}
+
+impl ChunkedWorld{
+    //fails with SizeMismatch if the chunks are smaller than 4 x 4, in smaller ones the cells a seam takes from the corners
+    //at either end touch and nothing keeps them from clashing. also fails if the template's constraints can't be met even on an empty chunk
+    pub fn new(template:&ConstraintSolver, chunk_width:usize, chunk_height:usize, world_seed:u64)->Result<Self, Error>{
+        if chunk_width<4 || chunk_height<4{
+            return Err(Error::SizeMismatch { expected: 4, found: chunk_width.min(chunk_height) });
+        }
+        let mut template = template.with_size(1, 1)?;
+        //chunks carry on into their neighbors, never back into themselves or into the edges of the template
+        template.set_edges(Edges::default())?;
+        template.set_boundary(Boundary::Open, Boundary::Open)?;
+        Ok(Self{template, chunk_width, chunk_height, world_seed, chunks:HashMap::new()})
+    }
+
+    //the seed a chunk is solved with, only depends on the world seed and where the chunk is
+    pub fn chunk_seed(&self, cx:i64, cy:i64)->u64{
+        mix(mix(self.world_seed ^ mix(cx as u64)) ^ cy as u64)
+    }
+
+    fn part_seed(&self, part:Part, cx:i64, cy:i64)->u64{
+        mix(self.chunk_seed(cx, cy) ^ part as u64)
+    }
+
+    pub fn get_chunk(&self, cx:i64, cy:i64)->Option<&Grid>{
+        self.chunks.get(&(cx, cy))
+    }
+
+    //the tile at a world position, None if its chunk hasn't been generated yet
+    pub fn get_tile(&self, x:i64, y:i64)->Option<TileType>{
+        let (w, h) = (self.chunk_width as i64, self.chunk_height as i64);
+        let chunk = self.chunks.get(&(x.div_euclid(w), y.div_euclid(h)))?;
+        Some(*chunk.get_sq(x.rem_euclid(w) as usize, y.rem_euclid(h) as usize))
+    }
+
+    //generates the chunk if it doesn't exist yet. a chunk always comes out the same for the same world seed,
+    //no matter which chunks were generated before it
+    pub fn generate_chunk(&mut self, cx:i64, cy:i64, selection_mode:&SelectionStrategy)->Result<&Grid, Error>{
+        if !self.chunks.contains_key(&(cx, cy)){
+            let grid = self.solve_chunk(cx, cy, selection_mode)?;
+            self.chunks.insert((cx, cy), grid);
+        }
+        Ok(&self.chunks[&(cx, cy)])
+    }
+
+    //generates every chunk from (cx_start, cy_start) up to but not including (cx_end, cy_end), row by row
+    pub fn generate_area(&mut self, cx_start:i64, cy_start:i64, cx_end:i64, cy_end:i64, selection_mode:&SelectionStrategy)->Result<(), Error>{
+        for cy in cy_start..cy_end{
+            for cx in cx_start..cx_end{
+                self.generate_chunk(cx, cy, selection_mode)?;
+            }
+        }
+        Ok(())
+    }
+
+    //a height x width piece of the world solved from seed with the cells at pins already known
+    fn solve(&self, height:usize, width:usize, seed:u64, pins:&[(usize, usize, TileType)], selection_mode:&SelectionStrategy)->Result<Grid, Error>{
+        let mut solver = self.template.with_size(height, width)?;
+        solver.set_seed(seed);
+        solver.pin(pins)?;
+        solver.collapse_fully_backtracking(selection_mode)?;
+        Ok(solver.get_grid().clone())
+    }
+
+    //the top left corner of chunk (cx, cy), solved as the middle of a 3 x 3 patch so the four seams leaving it fit each other
+    fn corner(&self, cx:i64, cy:i64, selection_mode:&SelectionStrategy)->Result<Corner, Error>{
+        let patch = self.solve(3, 3, self.part_seed(Part::Corner, cx, cy), &[], selection_mode)?;
+        Ok(Corner{tile:*patch.get_sq(1, 1), up:*patch.get_sq(1, 0), down:*patch.get_sq(1, 2), left:*patch.get_sq(0, 1), right:*patch.get_sq(2, 1)})
+    }
+
+    //the top row of chunk (cx, cy) from its corner up to and including the corner of the chunk to its right.
+    //solved as the middle of a 3 row strip, so there's something that fits on both sides of it
+    fn top_seam(&self, cx:i64, cy:i64, start:Corner, end:Corner, selection_mode:&SelectionStrategy)->Result<Vec<TileType>, Error>{
+        let w = self.chunk_width;
+        let pins = [(0, 1, start.tile), (1, 1, start.right), (w-1, 1, end.left), (w, 1, end.tile)];
+        let strip = self.solve(3, w+1, self.part_seed(Part::Top, cx, cy), &pins, selection_mode)?;
+        Ok((0..=w).map(|x| *strip.get_sq(x, 1)).collect())
+    }
+
+    //same as top_seam for the left column, down to the corner of the chunk below
+    fn left_seam(&self, cx:i64, cy:i64, start:Corner, end:Corner, selection_mode:&SelectionStrategy)->Result<Vec<TileType>, Error>{
+        let h = self.chunk_height;
+        let pins = [(1, 0, start.tile), (1, 1, start.down), (1, h-1, end.up), (1, h, end.tile)];
+        let strip = self.solve(h+1, 3, self.part_seed(Part::Left, cx, cy), &pins, selection_mode)?;
+        Ok((0..=h).map(|y| *strip.get_sq(1, y)).collect())
+    }
+
+    fn solve_chunk(&self, cx:i64, cy:i64, selection_mode:&SelectionStrategy)->Result<Grid, Error>{
+        let (w, h) = (self.chunk_width, self.chunk_height);
+        //the four corners around the chunk, every seam runs between two of them
+        let top_left = self.corner(cx, cy, selection_mode)?;
+        let top_right = self.corner(cx+1, cy, selection_mode)?;
+        let bottom_left = self.corner(cx, cy+1, selection_mode)?;
+        let bottom_right = self.corner(cx+1, cy+1, selection_mode)?;
+        //the chunk and the seams of the chunks to its right and below it, which close it off
+        let mut pins = Vec::new();
+        for (y, seam) in [(0, self.top_seam(cx, cy, top_left, top_right, selection_mode)?), (h, self.top_seam(cx, cy+1, bottom_left, bottom_right, selection_mode)?)]{
+            pins.extend(seam.into_iter().enumerate().map(|(x, t)| (x, y, t)));
+        }
+        for (x, seam) in [(0, self.left_seam(cx, cy, top_left, bottom_left, selection_mode)?), (w, self.left_seam(cx+1, cy, top_right, bottom_right, selection_mode)?)]{
+            pins.extend(seam.into_iter().enumerate().filter(|(y, _)| *y != 0 && *y != h).map(|(y, t)| (x, y, t)));
+        }
+        let solved = self.solve(h+1, w+1, self.chunk_seed(cx, cy), &pins, selection_mode)?;
+        let mut out = Grid::new(h, w);
+        for y in 0..h{
+            for x in 0..w{
+                *out.get_sqmut(x, y) = *solved.get_sq(x, y);
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[test]
+fn test_chunk_seams(){
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    //1s can't touch each other
+    let mut borders = HashMap::new();
+    borders.insert(0, vec![HashSet::from([0, 1]); 8]);
+    borders.insert(1, vec![HashSet::from([0]); 8]);
+    let template = ConstraintSolver::new_from_borders(Arc::new([0, 1]), borders, 1, 1);
+    //3 rows or columns leave no room between the corners
+    assert!(matches!(ChunkedWorld::new(&template, 4, 3, 9), Err(Error::SizeMismatch { expected:4, found:3 })));
+    assert!(ChunkedWorld::new(&template, 3, 4, 9).is_err());
+    let mut world = ChunkedWorld::new(&template, 4, 4, 9).unwrap();
+    world.generate_area(-1, -1, 2, 1, &SelectionStrategy::PurelyRandom).unwrap();
+    world.generate_chunk(5, 5, &SelectionStrategy::PurelyRandom).unwrap();
+    assert!(world.get_chunk(0, 1).is_none());
+    //stitched back together the seams have to hold up like any other cell
+    let mut whole = template.with_size(8, 12).unwrap();
+    let mut stitched = Grid::new(8, 12);
+    for y in 0..8{
+        for x in 0..12{
+            *stitched.get_sqmut(x, y) = world.get_tile(x as i64-4, y as i64-4).unwrap();
+        }
+    }
+    assert!(whole.pin_grid(&stitched).is_ok());
+    //backwards and starting far away from the rest, every chunk still comes out the same
+    let mut again = ChunkedWorld::new(&template, 4, 4, 9).unwrap();
+    again.generate_chunk(5, 5, &SelectionStrategy::PurelyRandom).unwrap();
+    for cy in (-1..1).rev(){
+        for cx in (-1..2).rev(){
+            again.generate_chunk(cx, cy, &SelectionStrategy::PurelyRandom).unwrap();
+        }
+    }
+    for (cx, cy) in world.chunks.keys(){
+        assert_eq!(again.get_chunk(*cx, *cy).unwrap().to_str(), world.get_chunk(*cx, *cy).unwrap().to_str());
+    }
+    assert_ne!(world.chunk_seed(0, 1), world.chunk_seed(1, 0));
+}
@@ -1,19 +1,140 @@
-use std::{collections::{HashMap, HashSet}, sync::Arc};
-use rand::{thread_rng, RngCore};
-use crate::{images::ByteImage, tile_set, utils};
-use crate::utils::OFFSETS;
-use crate::utils::MINUS_INDICES;
+use std::{collections::{HashMap, HashSet, VecDeque}, sync::Arc};
+use rand::{rngs::StdRng, thread_rng, RngCore, SeedableRng};
+use crate::{domain::Domain, error::Error, global::{Connected, GlobalConstraint, GlobalConstraintLoader, PathExists, TileCount}, hex::{self, HexTopology}, images::ByteImage, neighborhood::Neighborhood, statistics::SampleStatistics, symmetry::{Symmetry, TileVariants}, tile_set, topology::{GraphTopology, SquareTopology, Topology}, utils, voxel::{VoxelNeighborhood, VoxelTopology}};
+use serde_derive::{Deserialize, Serialize};
 pub use crate::tile_set::TileType;
 pub use crate::tile_set::TileSet;
+//what lies past an edge of the grid, nothing at all or the opposite edge
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Boundary{
+    #[default]
+    Open,
+    Wrap,
+}
+
+//how the cells of a grid sit next to each other, boundaries and edges only apply to square grids
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Layout{
+    #[default]
+    Square,
+    //rows of pointy topped hexes with every odd row pushed half a hex to the right, see HexTopology
+    Hex,
+    //layers of voxels stacked bottom to top, the rows of the grid go through every layer in turn so the height is
+    //layers times the height of one. see VoxelGrid::to_grid
+    Voxel{layers:usize, neighborhood:VoxelNeighborhood},
+    //cells joined any way at all, as a grid of one row. shared so handing out the topology doesn't copy every edge
+    Graph(#[serde(with = "shared_graph")] Arc<GraphTopology>),
+}
+
+//serde has no rc feature here, so the graph is (de)serialized through the Arc by hand
+mod shared_graph{
+    use std::sync::Arc;
+    use crate::topology::GraphTopology;
+
+    pub fn serialize<S:serde::Serializer>(graph:&Arc<GraphTopology>, serializer:S)->Result<S::Ok, S::Error>{
+        serde::Serialize::serialize(&**graph, serializer)
+    }
+
+    pub fn deserialize<'de, D:serde::Deserializer<'de>>(deserializer:D)->Result<Arc<GraphTopology>, D::Error>{
+        <GraphTopology as serde::Deserialize>::deserialize(deserializer).map(Arc::new)
+    }
+}
+
+impl Layout{
+    //the directions to every cell that touches another one. voxels and graphs have directions without offsets
+    pub fn neighborhood(&self)->Neighborhood{
+        match self{
+            Layout::Square=> Neighborhood::moore(),
+            Layout::Hex=> Neighborhood::hex(),
+            Layout::Voxel{neighborhood, ..}=> Neighborhood::directions(neighborhood.opposites()).expect("voxel neighborhoods are symmetric"),
+            Layout::Graph(graph)=> Neighborhood::directions((0..graph.direction_count()).map(|d| graph.inverse(d)).collect()).expect("graph directions come with their inverse"),
+        }
+    }
+
+    //fails with SizeMismatch unless a height x width grid can be laid out this way: voxels need a whole number of
+    //rows in every layer and graphs a cell for every node
+    pub fn check_size(&self, height:usize, width:usize)->Result<(), Error>{
+        match self{
+            Layout::Voxel{layers, ..} if *layers == 0 || !height.is_multiple_of(*layers)=>{
+                Err(Error::SizeMismatch { expected: height.div_ceil((*layers).max(1))*layers, found: height })
+            }
+            Layout::Graph(graph) if height*width != graph.cell_count()=>{
+                Err(Error::SizeMismatch { expected: graph.cell_count(), found: height*width })
+            }
+            _=> Ok(()),
+        }
+    }
+}
+
+//what the constraints see past an open edge of the grid
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum EdgeCondition{
+    //nothing, constraints ignore the edge
+    #[default]
+    Free,
+    //a virtual tile that's treated like a real neighbor
+    Tile(TileType),
+    //any one of these tiles, a cell only has to fit next to one of them
+    OneOf(Vec<TileType>),
+    //nothing past the edge, but only these tiles may go in the cells along it
+    Allowed(Vec<TileType>),
+}
+
+impl EdgeCondition{
+    fn tiles(&self)->Option<Vec<TileType>>{
+        match self{
+            Self::Free | Self::Allowed(_)=> None,
+            Self::Tile(t)=> Some(vec![*t]),
+            Self::OneOf(ts)=> Some(ts.clone()),
+        }
+    }
+
+    fn allows(&self, tile:TileType)->bool{
+        match self{
+            Self::Allowed(ts)=> ts.contains(&tile),
+            _=> true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Edges{
+    pub left:EdgeCondition,
+    pub right:EdgeCondition,
+    pub top:EdgeCondition,
+    pub bottom:EdgeCondition,
+}
+
+impl Edges{
+    pub fn all(condition:EdgeCondition)->Self{
+        Self{left:condition.clone(), right:condition.clone(), top:condition.clone(), bottom:condition}
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Grid{
     values:Box<[TileType]>, 
     height:usize, 
     width:usize,
+    //boundaries of the left and right edges and of the top and bottom ones
+    horizontal:Boundary,
+    vertical:Boundary,
+    edges:Edges,
+    layout:Layout,
 }
 
 impl Grid{
     pub fn new(height:usize, width:usize)->Self{
+        Self::blank(height, width, Layout::Square)
+    }
+
+    //fails if the size doesn't fit the layout, see Layout::check_size
+    pub fn new_with_layout(height:usize, width:usize, layout:Layout)->Result<Self, Error>{
+        layout.check_size(height, width)?;
+        Ok(Self::blank(height, width, layout))
+    }
+
+    fn blank(height:usize, width:usize, layout:Layout)->Self{
         let mut tmp: Vec<TileType> = Vec::new();
         tmp.reserve_exact(height*width);
         for _ in 0..width{
@@ -22,7 +143,137 @@ impl Grid{
             }
         }
         let values:Box<[TileType]> = tmp.into();
-        Self{values, height, width}
+        Self{values, height, width, horizontal:Boundary::Open, vertical:Boundary::Open, edges:Edges::default(), layout}
+    }
+
+    //a grid of one row with a cell for every cell of graph
+    pub fn new_graph(graph:GraphTopology)->Self{
+        Self::blank(1, graph.cell_count(), Layout::Graph(Arc::new(graph)))
+    }
+
+    //a square grid holding data, row by row
+    pub fn new_from_data(data:&[TileType], height:usize, width:usize)->Result<Self, Error>{
+        if data.len() != height*width{
+            return Err(Error::SizeMismatch { expected: height*width, found: data.len() });
+        }
+        let mut out = Self::new(height, width);
+        out.values = data.into();
+        Ok(out)
+    }
+
+    pub fn get_layout(&self)->&Layout{
+        &self.layout
+    }
+
+    //every cell, row by row
+    pub fn get_data(&self)->&[TileType]{
+        &self.values
+    }
+
+    //with both set to Wrap the grid is a torus and tiles seamlessly
+    pub fn set_boundary(&mut self, horizontal:Boundary, vertical:Boundary){
+        self.horizontal = horizontal;
+        self.vertical = vertical;
+    }
+
+    pub fn get_boundary(&self)->(Boundary, Boundary){
+        (self.horizontal, self.vertical)
+    }
+
+    //only used along axes that don't wrap
+    pub fn set_edges(&mut self, edges:Edges){
+        self.edges = edges;
+    }
+
+    pub fn get_edges(&self)->&Edges{
+        &self.edges
+    }
+
+    //the virtual tiles dx, dy away from (x, y) when that's past an open edge with a condition on it, None otherwise.
+    //past a corner it can be a tile of either edge, so a cell in the corner only has to fit one of them
+    pub fn edge_tiles(&self, x:usize, y:usize, dx:isize, dy:isize)->Option<Vec<TileType>>{
+        if self.layout != Layout::Square{
+            return None;
+        }
+        let sx = x as isize+dx;
+        let sy = y as isize+dy;
+        let horizontal = if self.horizontal == Boundary::Wrap || (0..self.width as isize).contains(&sx){
+            None
+        } else if sx<0{
+            self.edges.left.tiles()
+        } else{
+            self.edges.right.tiles()
+        };
+        let vertical = if self.vertical == Boundary::Wrap || (0..self.height as isize).contains(&sy){
+            None
+        } else if sy<0{
+            self.edges.top.tiles()
+        } else{
+            self.edges.bottom.tiles()
+        };
+        match (horizontal, vertical){
+            (Some(mut h), Some(v))=>{
+                for t in v{
+                    if !h.contains(&t){
+                        h.push(t);
+                    }
+                }
+                Some(h)
+            }
+            (h, v)=> h.or(v),
+        }
+    }
+
+    //false if (x, y) lies along an open edge whose allowed set doesn't have tile
+    pub fn edge_allows(&self, x:usize, y:usize, tile:TileType)->bool{
+        if self.layout != Layout::Square{
+            return true;
+        }
+        if self.horizontal == Boundary::Open && ((x == 0 && !self.edges.left.allows(tile)) || (x+1 == self.width && !self.edges.right.allows(tile))){
+            return false;
+        }
+        !(self.vertical == Boundary::Open && ((y == 0 && !self.edges.top.allows(tile)) || (y+1 == self.height && !self.edges.bottom.allows(tile))))
+    }
+
+    //the cells of the grid and the directions of neighborhood between them, with the grid's boundaries.
+    //on a hex grid the offsets of neighborhood are axial, voxels and graphs always go in their own directions
+    pub fn topology(&self, neighborhood:&Neighborhood)->Arc<dyn Topology>{
+        match &self.layout{
+            Layout::Square=> Arc::new(SquareTopology::of_grid(self, neighborhood.clone())),
+            Layout::Hex=> Arc::new(HexTopology::new(self.height, self.width, neighborhood.clone())),
+            Layout::Voxel{layers, neighborhood}=> Arc::new(VoxelTopology::new(*layers, self.height/layers, self.width, *neighborhood)),
+            Layout::Graph(graph)=> graph.clone(),
+        }
+    }
+
+    //cells are numbered row by row, the same as Topology and the domains of the solver
+    pub fn index(&self, x:usize, y:usize)->usize{
+        y*self.width+x
+    }
+
+    pub fn position(&self, cell:usize)->(usize, usize){
+        (cell%self.width, cell/self.width)
+    }
+
+    //the cell dx, dy away from (x, y), None if that's past an open edge
+    pub fn neighbor(&self, x:usize, y:usize, dx:isize, dy:isize)->Option<(usize, usize)>{
+        Some((Self::step(x, dx, self.width, self.horizontal)?, Self::step(y, dy, self.height, self.vertical)?))
+    }
+
+    pub(crate) fn step(v:usize, d:isize, size:usize, boundary:Boundary)->Option<usize>{
+        let s = v as isize+d;
+        match boundary{
+            Boundary::Open=> (s>= 0 && s<size as isize).then_some(s as usize),
+            Boundary::Wrap=> Some(s.rem_euclid(size as isize) as usize),
+        }
+    }
+
+    pub fn get_height(&self)->usize{
+        self.height
+    }
+
+    pub fn get_width(&self)->usize{
+        self.width
     }
 
     pub fn get_sq(&self,x:usize, y:usize)->&TileType{
@@ -48,20 +299,23 @@ impl Grid{
         out
     }
 
-    pub fn draw_as_byte_image(&self,tileset:&TileSet)->ByteImage{
+    pub fn draw_as_byte_image(&self,tileset:&TileSet)->Result<ByteImage, Error>{
+        if self.layout == Layout::Hex{
+            return hex::draw_hexes(self, tileset);
+        }
         let mut out = ByteImage::new_from_color(raylib::color::Color::BLACK, self.height * tileset.tile_size, self.width*tileset.tile_size);
         for y in 0..self.height{
             for x in 0..self.width{
-                out.draw_byte_image_to(&tileset.tiles[self.get_sq(x, y)],(x*tileset.tile_size) as isize,(y*tileset.tile_size) as isize);
+                out.draw_byte_image_to(tileset.get_tile(*self.get_sq(x, y))?,(x*tileset.tile_size) as isize,(y*tileset.tile_size) as isize);
             }
         }
-        out
+        Ok(out)
     }   
 
     //this is slow, I can't figure out how to make it much faster uh, skill issue on my part. 
-    pub fn draw_as_texture(&self, tileset:&TileSet, thread:&mut raylib::RaylibThread, handle:&mut raylib::RaylibHandle)->Result<raylib::prelude::Texture2D, String>{
-        let tmp = self.draw_as_byte_image(tileset);
-        let out = handle.load_texture_from_image(thread,&tmp.to_image())?;
+    pub fn draw_as_texture(&self, tileset:&TileSet, thread:&mut raylib::RaylibThread, handle:&mut raylib::RaylibHandle)->Result<raylib::prelude::Texture2D, Error>{
+        let tmp = self.draw_as_byte_image(tileset)?;
+        let out = handle.load_texture_from_image(thread,&tmp.to_image()).map_err(Error::Graphics)?;
         Ok(out)
     }
     pub fn reset(&mut self){
@@ -69,68 +323,118 @@ impl Grid{
             *i = -1;
         }
     }
+
+    //true once every cell touching (x, y) has a value
+    pub fn neighbors_defined(&self, x:usize, y:usize)->bool{
+        self.topology(&self.layout.neighborhood()).neighbors(self.index(x, y)).iter().all(|(_, other)| self.values[*other] != -1)
+    }
+}
+
+//for every tile, the tiles allowed next to it in each direction
+pub type Borders = HashMap<TileType,Vec<HashSet<TileType>>>;
+
+//a rule about which tiles may go where, it only ever sees the cells that already have a value
+pub trait Constraint: std::fmt::Debug{
+    //the name the constraint is saved under, a loader has to be registered under the same name to read it back
+    fn tag(&self)->&'static str;
+
+    //returns true if the state is valid, false if it's invalid
+    fn check(&self, grid:&Grid, tile_type:TileType, x:usize, y:usize)->bool;
+
+    //removes every candidate (indices into allowed_states) that can't go at x, y
+    fn filter_allowed(&self, grid:&Grid, allowed_states:&[TileType], domain:&mut Domain, x:usize, y:usize){
+        for i in domain.clone().iter(){
+            if !self.check(grid, allowed_states[i], x, y){
+                domain.remove(i);
+            }
+        }
+    }
+
+    //true once filling in more of the grid can't change what check says at x, y. by default that's when the
+    //cells around are all set, constraints that don't look at their neighbors can say so sooner
+    fn decided(&self, grid:&Grid, x:usize, y:usize)->bool{
+        grid.neighbors_defined(x, y)
+    }
+
+    //everything a loader needs to rebuild the constraint, the tag gets added by GridConstraint::serialize
+    fn to_json(&self)->Result<serde_json::Value, Error>;
+
+    //adjacency table the solver turns into bitmasks instead of calling check, only border constraints have one.
+    //borders[tile][i] holds the tiles allowed in direction i of the neighborhood
+    fn borders(&self)->Option<(&Borders, &Neighborhood)>{
+        None
+    }
 }
 
 #[derive(Clone)]
 pub struct GridConstraint{
-    //returns true if the state is valid, false if it's invalid
-    pub constraints_sat:Arc<dyn Fn(&Grid,TileType, usize, usize)->bool>,
-    pub debug_fn:Arc<dyn Fn()->String>,
-    pub serialize_fn:Arc<dyn Fn()->Result<String, Box<dyn std::error::Error>>>
+    inner:Arc<dyn Constraint>,
 }
 
 impl std::fmt::Debug for GridConstraint{
-    fn fmt(&self, formatter:&mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> { let _ = formatter.write_str(&(self.debug_fn)()); Ok(())}
+    fn fmt(&self, formatter:&mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> { self.inner.fmt(formatter)}
 }
 
 impl GridConstraint{
-    pub fn new(func:Arc<dyn Fn(&Grid, TileType,usize, usize)->bool>,debug_fn:Arc<dyn Fn()->String>, serialize_fn:Arc<dyn Fn()->Result<String, Box<dyn std::error::Error>>>)->Self{
-        Self{constraints_sat:func, debug_fn, serialize_fn}
+    pub fn new(constraint:impl Constraint+'static)->Self{
+        Self{inner:Arc::new(constraint)}
     }
 
-    // tile types; directions within tile tiles; allowed types per direction
+    // tile types; directions within tile tiles; allowed types per direction.
+    //a tile with fewer than 8 sets doesn't constrain the directions it's missing
     pub fn new_from_borders(constraints:HashMap<TileType,Vec<HashSet<TileType>>>)->Self{
-        let debug_constraints = constraints.clone();
-        let ser_constraints = constraints.clone();
-        let func = move |grid:&Grid, tile_type:TileType, x:usize, y:usize|{
-            for i in 0..8{
-                let (dx,dy) = OFFSETS[i];
-                if x as isize+dx<0 || y as isize+dy<0{
-                    continue;
-                }
-                if (x as isize +dx) as usize>= grid.width || (y as isize+dy) as usize>= grid.height{
-                    continue;
-                }
-                let tt = *grid.get_sq((x as isize+dx) as usize, (y as isize+dy) as usize);
-                if tt< 0{
-                    continue;
-                }
-                if tt as usize>=constraints.len(){
-                    panic!("error found out of bounds tile_type");
-                } 
-                let tc = constraints.get(&tt).unwrap();
-                let j = MINUS_INDICES[i];
-                if !tc[j].contains(&tile_type){
-                    //println!("error: constraint not satisfied, {} not allowed {}  of {}", tile_type, OFFSET_NAMES[j], tt);
-                    return false
-                }
-            }
-            true
-        };
-        let debug_fn = move||{
-            format!("{:#?}", debug_constraints)
-        };
-        let serialize_fn= move||{
-            let tmp = serde_json::to_string_pretty(&ser_constraints);
-            if let Ok(t) = tmp{
-                Ok(t)
-            }  else{
-                let t = tmp.unwrap_err();
-                Err(Box::new(t) as Box<dyn std::error::Error>)
-            }
-       
-        };
-        Self{constraints_sat:Arc::new(func), debug_fn:Arc::new(debug_fn), serialize_fn:Arc::new(serialize_fn)}
+        Self::new(BorderConstraint{borders:constraints, neighborhood:Neighborhood::moore()})
+    }
+
+    //like new_from_borders but with one set per direction of neighborhood instead of the 8 around,
+    //fails if any tile has a different number of sets
+    pub fn new_from_borders_with_neighborhood(constraints:HashMap<TileType,Vec<HashSet<TileType>>>, neighborhood:Neighborhood)->Result<Self, Error>{
+        if let Some((tile, sets)) = constraints.iter().find(|(_, sets)| sets.len() != neighborhood.len()){
+            return Err(Error::InvalidNeighborhood(format!("tile {tile} has {} sets of borders for {} directions", sets.len(), neighborhood.len())));
+        }
+        Ok(Self::new(BorderConstraint{borders:constraints, neighborhood}))
+    }
+
+    //satisfied only if every constraint is, an empty list is always satisfied
+    pub fn all(constraints:Vec<GridConstraint>)->Self{
+        Self::new(AllConstraint{constraints})
+    }
+
+    //satisfied if at least one constraint is, an empty list is never satisfied
+    pub fn any(constraints:Vec<GridConstraint>)->Self{
+        Self::new(AnyConstraint{constraints})
+    }
+
+    //satisfied when the constraint isn't. constraints only see the neighbors that are already defined, so this can't
+    //say anything until the constraint is decided, before that it allows everything
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(constraint:GridConstraint)->Self{
+        Self::new(NotConstraint{constraint})
+    }
+
+    //only applies the constraint to cells holding tile
+    pub fn when_tile(tile:TileType, constraint:GridConstraint)->Self{
+        Self::new(WhenTileConstraint{tile, constraint})
+    }
+
+    //only applies the constraint inside the rectangle from (x_start, y_start) up to but not including (x_end, y_end)
+    pub fn in_region(x_start:usize, y_start:usize, x_end:usize, y_end:usize, constraint:GridConstraint)->Self{
+        Self::new(InRegionConstraint{x_start, y_start, x_end, y_end, constraint})
+    }
+
+    pub fn tag(&self)->&'static str{
+        self.inner.tag()
+    }
+
+    pub fn borders(&self)->Option<(&Borders, &Neighborhood)>{
+        self.inner.borders()
+    }
+
+    //saved as {tag:data}, which is what ConstraintRegistry::load expects
+    pub fn serialize(&self)->Result<serde_json::Value, Error>{
+        let mut out = serde_json::Map::new();
+        out.insert(self.inner.tag().to_string(), self.inner.to_json()?);
+        Ok(serde_json::Value::Object(out))
     }
 
     //returns true if the state is valid, false if it's invalid
@@ -138,8 +442,17 @@ impl GridConstraint{
         if test_value == -1{
             return true;
         }
-        (self.constraints_sat)(grid,test_value, x,y)
+        self.inner.check(grid,test_value, x,y)
+    }
+
+    pub fn filter_allowed(&self, grid:&Grid, allowed_states:&[TileType], domain:&mut Domain, x:usize, y:usize){
+        self.inner.filter_allowed(grid, allowed_states, domain, x, y);
     }
+
+    pub fn decided(&self, grid:&Grid, x:usize, y:usize)->bool{
+        self.inner.decided(grid, x, y)
+    }
+
     pub fn get_allowed_states(&self,grid:&Grid,allowed_states:&[TileType],x:usize, y:usize)->Vec<TileType>{
         let mut out = vec![];
         for i in allowed_states{
@@ -151,131 +464,996 @@ impl GridConstraint{
     }
 }
 
-pub enum SelectionStrategy{PurelyRandom, FromDistribution{distribution:Box<[(TileType, f64)]>}, MinimizeEntropy, MaximizeEntropy}
+#[derive(Clone, Debug)]
+pub struct BorderConstraint{
+    pub borders:HashMap<TileType,Vec<HashSet<TileType>>>,
+    pub neighborhood:Neighborhood,
+}
+
+impl Constraint for BorderConstraint{
+    fn tag(&self)->&'static str{
+        "borders"
+    }
+
+    fn check(&self, grid:&Grid, tile_type:TileType, x:usize, y:usize)->bool{
+        let topology = grid.topology(&self.neighborhood);
+        for (dir, other) in topology.neighbors(grid.index(x, y)){
+            let tt = grid.values[other];
+            if tt< 0{
+                continue;
+            }
+            //a tile with no borders of its own doesn't constrain what goes next to it
+            let Some(tc) = self.borders.get(&tt) else{
+                continue;
+            };
+            if tc.get(topology.inverse(dir)).is_some_and(|allowed| !allowed.contains(&tile_type)){
+                //println!("error: constraint not satisfied, {} not allowed {}  of {}", tile_type, OFFSET_NAMES[j], tt);
+                return false
+            }
+        }
+        //past an open edge there's no cell, only the virtual tiles of its edge condition
+        for (dir, (dx, dy)) in self.neighborhood.offsets().iter().enumerate(){
+            let Some(outside) = grid.edge_tiles(x, y, *dx, *dy) else{
+                continue;
+            };
+            let j = topology.inverse(dir);
+            if !outside.iter().any(|t| self.borders.get(t).is_some_and(|tc| tc.get(j).is_some_and(|allowed| allowed.contains(&tile_type)))){
+                return false;
+            }
+        }
+        true
+    }
+
+    fn to_json(&self)->Result<serde_json::Value, Error>{
+        Ok(serde_json::json!({"neighborhood":self.neighborhood, "borders":self.borders}))
+    }
+
+    fn borders(&self)->Option<(&Borders, &Neighborhood)>{
+        Some((&self.borders, &self.neighborhood))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AllConstraint{
+    pub constraints:Vec<GridConstraint>,
+}
+
+impl Constraint for AllConstraint{
+    fn tag(&self)->&'static str{
+        "all"
+    }
+
+    fn check(&self, grid:&Grid, tile_type:TileType, x:usize, y:usize)->bool{
+        self.constraints.iter().all(|c| c.check_constraint(grid, tile_type, x, y))
+    }
+
+    fn filter_allowed(&self, grid:&Grid, allowed_states:&[TileType], domain:&mut Domain, x:usize, y:usize){
+        for c in &self.constraints{
+            c.filter_allowed(grid, allowed_states, domain, x, y);
+        }
+    }
+
+    fn decided(&self, grid:&Grid, x:usize, y:usize)->bool{
+        self.constraints.iter().all(|c| c.decided(grid, x, y))
+    }
+
+    fn to_json(&self)->Result<serde_json::Value, Error>{
+        Ok(serde_json::Value::Array(self.constraints.iter().map(|c| c.serialize()).collect::<Result<_, _>>()?))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AnyConstraint{
+    pub constraints:Vec<GridConstraint>,
+}
+
+impl Constraint for AnyConstraint{
+    fn tag(&self)->&'static str{
+        "any"
+    }
+
+    fn check(&self, grid:&Grid, tile_type:TileType, x:usize, y:usize)->bool{
+        self.constraints.iter().any(|c| c.check_constraint(grid, tile_type, x, y))
+    }
+
+    fn filter_allowed(&self, grid:&Grid, allowed_states:&[TileType], domain:&mut Domain, x:usize, y:usize){
+        let mut out = Domain::empty(allowed_states.len());
+        for c in &self.constraints{
+            let mut d = domain.clone();
+            c.filter_allowed(grid, allowed_states, &mut d, x, y);
+            out.union_with(&d);
+        }
+        *domain = out;
+    }
+
+    fn decided(&self, grid:&Grid, x:usize, y:usize)->bool{
+        self.constraints.iter().all(|c| c.decided(grid, x, y))
+    }
+
+    fn to_json(&self)->Result<serde_json::Value, Error>{
+        Ok(serde_json::Value::Array(self.constraints.iter().map(|c| c.serialize()).collect::<Result<_, _>>()?))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct NotConstraint{
+    pub constraint:GridConstraint,
+}
+
+impl Constraint for NotConstraint{
+    fn tag(&self)->&'static str{
+        "not"
+    }
+
+    fn check(&self, grid:&Grid, tile_type:TileType, x:usize, y:usize)->bool{
+        !self.constraint.decided(grid, x, y) || !self.constraint.check_constraint(grid, tile_type, x, y)
+    }
+
+    //keeps exactly what the constraint would have removed
+    fn filter_allowed(&self, grid:&Grid, allowed_states:&[TileType], domain:&mut Domain, x:usize, y:usize){
+        if !self.constraint.decided(grid, x, y){
+            return;
+        }
+        let mut rejected = domain.clone();
+        self.constraint.filter_allowed(grid, allowed_states, &mut rejected, x, y);
+        for i in rejected.iter(){
+            domain.remove(i);
+        }
+    }
+
+    fn decided(&self, grid:&Grid, x:usize, y:usize)->bool{
+        self.constraint.decided(grid, x, y)
+    }
+
+    fn to_json(&self)->Result<serde_json::Value, Error>{
+        self.constraint.serialize()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WhenTileConstraint{
+    pub tile:TileType,
+    pub constraint:GridConstraint,
+}
+
+impl Constraint for WhenTileConstraint{
+    fn tag(&self)->&'static str{
+        "when_tile"
+    }
+
+    fn check(&self, grid:&Grid, tile_type:TileType, x:usize, y:usize)->bool{
+        tile_type != self.tile || self.constraint.check_constraint(grid, tile_type, x, y)
+    }
+
+    fn decided(&self, grid:&Grid, x:usize, y:usize)->bool{
+        self.constraint.decided(grid, x, y)
+    }
+
+    fn to_json(&self)->Result<serde_json::Value, Error>{
+        Ok(serde_json::json!({"tile":self.tile, "constraint":self.constraint.serialize()?}))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct InRegionConstraint{
+    pub x_start:usize, 
+    pub y_start:usize, 
+    pub x_end:usize, 
+    pub y_end:usize,
+    pub constraint:GridConstraint,
+}
+
+impl InRegionConstraint{
+    fn contains(&self, x:usize, y:usize)->bool{
+        x>= self.x_start && y>= self.y_start && x<self.x_end && y<self.y_end
+    }
+}
+
+impl Constraint for InRegionConstraint{
+    fn tag(&self)->&'static str{
+        "in_region"
+    }
+
+    fn check(&self, grid:&Grid, tile_type:TileType, x:usize, y:usize)->bool{
+        !self.contains(x, y) || self.constraint.check_constraint(grid, tile_type, x, y)
+    }
+
+    fn filter_allowed(&self, grid:&Grid, allowed_states:&[TileType], domain:&mut Domain, x:usize, y:usize){
+        if self.contains(x, y){
+            self.constraint.filter_allowed(grid, allowed_states, domain, x, y);
+        }
+    }
+
+    //outside the region it always allows everything
+    fn decided(&self, grid:&Grid, x:usize, y:usize)->bool{
+        !self.contains(x, y) || self.constraint.decided(grid, x, y)
+    }
+
+    fn to_json(&self)->Result<serde_json::Value, Error>{
+        Ok(serde_json::json!({"x_start":self.x_start, "y_start":self.y_start, "x_end":self.x_end, "y_end":self.y_end, "constraint":self.constraint.serialize()?}))
+    }
+}
+
+//rebuilds a constraint from the data its to_json produced, children get loaded through the registry that is passed in
+pub type ConstraintLoader = fn(&serde_json::Value, &ConstraintRegistry)->Result<GridConstraint, Error>;
+
+//maps the tag a constraint is saved under to the function that loads it back, new kinds of constraint get registered here
+#[derive(Clone)]
+pub struct ConstraintRegistry{
+    loaders:HashMap<String, ConstraintLoader>,
+    global_loaders:HashMap<String, GlobalConstraintLoader>,
+}
+
+impl Default for ConstraintRegistry{
+    fn default()->Self{
+        Self::new()
+    }
+}
+
+fn malformed(msg:&str)->Error{
+    Error::Serialization(<serde_json::Error as serde::de::Error>::custom(msg))
+}
+
+fn field<'a>(value:&'a serde_json::Value, name:&str)->Result<&'a serde_json::Value, Error>{
+    value.get(name).ok_or_else(|| malformed(&format!("missing field {name}")))
+}
+
+fn load_list(value:&serde_json::Value, registry:&ConstraintRegistry)->Result<Vec<GridConstraint>, Error>{
+    value.as_array().ok_or_else(|| malformed("expected a list of constraints"))?.iter().map(|c| registry.load(c)).collect()
+}
+
+impl ConstraintRegistry{
+    //a registry that knows every constraint in this file
+    pub fn new()->Self{
+        let mut out = Self{loaders:HashMap::new(), global_loaders:HashMap::new()};
+        out.register("borders", |v, _|{
+            //older saves are only the borders, for the 8 cells around
+            let Some(borders) = v.get("borders") else{
+                return GridConstraint::new_from_borders_with_neighborhood(serde_json::from_value(v.clone())?, Neighborhood::moore());
+            };
+            let neighborhood:Neighborhood = serde_json::from_value(field(v, "neighborhood")?.clone())?;
+            GridConstraint::new_from_borders_with_neighborhood(serde_json::from_value(borders.clone())?, neighborhood)
+        });
+        out.register("all", |v, r|{
+            Ok(GridConstraint::all(load_list(v, r)?))
+        });
+        out.register("any", |v, r|{
+            Ok(GridConstraint::any(load_list(v, r)?))
+        });
+        out.register("not", |v, r|{
+            Ok(GridConstraint::not(r.load(v)?))
+        });
+        out.register("when_tile", |v, r|{
+            Ok(GridConstraint::when_tile(serde_json::from_value(field(v, "tile")?.clone())?, r.load(field(v, "constraint")?)?))
+        });
+        out.register("in_region", |v, r|{
+            let coord = |name:&str|->Result<usize, Error>{ Ok(serde_json::from_value(field(v, name)?.clone())?) };
+            Ok(GridConstraint::in_region(coord("x_start")?, coord("y_start")?, coord("x_end")?, coord("y_end")?, r.load(field(v, "constraint")?)?))
+        });
+        out.register_global("tile_count", TileCount::load);
+        out.register_global("connected", Connected::load);
+        out.register_global("path_exists", PathExists::load);
+        out
+    }
+
+    pub fn register(&mut self, tag:&str, loader:ConstraintLoader){
+        self.loaders.insert(tag.to_string(), loader);
+    }
+
+    pub fn register_global(&mut self, tag:&str, loader:GlobalConstraintLoader){
+        self.global_loaders.insert(tag.to_string(), loader);
+    }
+
+    //global constraints are saved as {tag:data} too
+    pub fn load_global(&self, value:&serde_json::Value)->Result<Arc<dyn GlobalConstraint>, Error>{
+        let Some(obj) = value.as_object().filter(|o| o.len() == 1) else{
+            return Err(malformed("a constraint has to be an object with a single tag"));
+        };
+        let (tag, data) = obj.iter().next().expect("object has one entry");
+        let loader = self.global_loaders.get(tag).ok_or_else(|| Error::UnknownConstraint(tag.clone()))?;
+        loader(data)
+    }
+
+    pub fn load(&self, value:&serde_json::Value)->Result<GridConstraint, Error>{
+        let Some(obj) = value.as_object().filter(|o| o.len() == 1) else{
+            return Err(malformed("a constraint has to be an object with a single tag"));
+        };
+        let (tag, data) = obj.iter().next().expect("object has one entry");
+        let loader = self.loaders.get(tag).ok_or_else(|| Error::UnknownConstraint(tag.clone()))?;
+        loader(data, self)
+    }
+
+    pub fn load_str(&self, data:&str)->Result<GridConstraint, Error>{
+        self.load(&serde_json::from_str(data)?)
+    }
+}
+
+//everything needed to rebuild a solver, see ConstraintSolver::save_setup
+#[derive(Serialize, Deserialize)]
+struct SolverSetup{
+    height:usize, 
+    width:usize,
+    #[serde(default)]
+    layout:Layout,
+    allowed_states:Vec<TileType>,
+    constraints:Vec<serde_json::Value>,
+    #[serde(default)]
+    globals:Vec<serde_json::Value>,
+    #[serde(default)]
+    pins:Vec<(usize, usize, TileType)>,
+    #[serde(default)]
+    boundary:(Boundary, Boundary),
+    #[serde(default)]
+    edges:Edges,
+    weights:Vec<(TileType, f64)>,
+    #[serde(default)]
+    statistics:Option<SampleStatistics>,
+    seed:u64,
+    grid:Vec<TileType>,
+}
+
+//FromSample picks cells like MinimizeEntropy but weighs each tile by how well it fits its placed neighbors in the sample the
+//solver learned from, see SampleStatistics::likelihood. solvers that weren't learned from data fall back to their weights
+pub enum SelectionStrategy{PurelyRandom, FromDistribution{distribution:Box<[(TileType, f64)]>}, MinimizeEntropy, MaximizeEntropy, FromSample}
+
+//everything that gets changed while solving, so a failed decision can be rolled back
+#[derive(Clone, Debug)]
+enum TrailEntry{Assigned(usize), Domain(usize, Domain)}
+
+#[derive(Clone, Debug)]
+struct Decision{
+    cell:usize,
+    value:TileType,
+    trail_len:usize,
+}
 
 #[derive(Clone, Debug)]
 pub struct ConstraintSolver{
     grid:Grid, 
     constraints:Vec<GridConstraint>,
+    globals:Vec<Arc<dyn GlobalConstraint>>,
     allowed_states:Arc<[TileType]>, 
-    allowed_neighbors_cache:HashMap<(usize,usize), Vec<TileType>>
+    state_index:HashMap<TileType, usize>,
+    //remaining candidates of every cell, as indices into allowed_states
+    domains:Vec<Domain>,
+    //every direction any border constraint looks in, everything around if there are none
+    neighborhood:Neighborhood,
+    //the cells of the grid and the directions of neighborhood between them, everything that looks at neighbors goes through it
+    topology:Arc<dyn Topology>,
+    //adjacency[tile_idx*neighborhood.len()+dir] is every tile that may sit in direction dir of tile_idx, None if there are no border constraints
+    adjacency:Option<Arc<[Domain]>>,
+    trail:Vec<TrailEntry>,
+    decisions:Vec<Decision>,
+    //cells fixed before solving, neither backtracking nor reset touch them
+    pins:Vec<(usize, usize, TileType)>,
+    weights:HashMap<TileType, f64>,
+    //what the solver was learned from, if it was
+    statistics:Option<Arc<SampleStatistics>>,
+    //every random choice goes through rng, so a run can be replayed from its seed
+    seed:u64,
+    rng:StdRng,
 }
 
-impl ConstraintSolver{
+impl ConstraintSolver{
+
+    pub fn new(height:usize,width:usize, allowed_states:Arc<[TileType]>)->Self{
+        Self::new_with_constraints(height, width, allowed_states, Vec::new())
+    }
+
+    pub fn new_with_constraints(height:usize, width:usize,allowed_states:Arc<[TileType]>, constraints:Vec<GridConstraint>)->Self{
+        Self::build(Layout::Square, height, width, allowed_states, constraints)
+    }
+
+    //like new_with_constraints for a grid laid out some other way, the border constraints look in the directions of that layout.
+    //fails if the size doesn't fit the layout, see Layout::check_size
+    pub fn new_with_layout(layout:Layout, height:usize, width:usize, allowed_states:Arc<[TileType]>, constraints:Vec<GridConstraint>)->Result<Self, Error>{
+        layout.check_size(height, width)?;
+        Ok(Self::build(layout, height, width, allowed_states, constraints))
+    }
+
+    fn build(layout:Layout, height:usize, width:usize, allowed_states:Arc<[TileType]>, constraints:Vec<GridConstraint>)->Self{
+        let state_index:HashMap<TileType, usize> = allowed_states.iter().enumerate().map(|(i, t)| (*t, i)).collect();
+        let (neighborhood, adjacency) = Self::build_adjacency(&constraints, &layout, &allowed_states, &state_index);
+        let seed = thread_rng().next_u64();
+        let grid = Grid::blank(height, width, layout);
+        let topology = grid.topology(&neighborhood);
+        let mut out = Self{grid, constraints, globals:Vec::new(), pins:Vec::new(), allowed_states, state_index, domains:Vec::new(), neighborhood, topology, adjacency, trail:Vec::new(), decisions:Vec::new(), weights:HashMap::new(), statistics:None, seed, rng:StdRng::seed_from_u64(seed)};
+        out.domains = out.initial_domains();
+        out
+    }
+
+    //a fresh solver with the same tiles, constraints, weights and seed for a grid of another size.
+    //global constraints and pins are left out since they're about this grid in particular.
+    //fails if the size doesn't fit the layout, which can only happen to voxels and graphs
+    pub fn with_size(&self, height:usize, width:usize)->Result<Self, Error>{
+        self.with_layout(self.grid.layout.clone(), height, width)
+    }
+
+    //same as with_size, but the new grid can also be laid out another way
+    pub fn with_layout(&self, layout:Layout, height:usize, width:usize)->Result<Self, Error>{
+        let mut out = Self::new_with_layout(layout, height, width, self.allowed_states.clone(), self.constraints.clone())?;
+        out.weights = self.weights.clone();
+        out.statistics = self.statistics.clone();
+        out.set_seed(self.seed);
+        let (horizontal, vertical) = self.grid.get_boundary();
+        out.grid.set_edges(self.grid.edges.clone());
+        out.grid.set_boundary(horizontal, vertical);
+        out.topology = out.grid.topology(&out.neighborhood);
+        out.domains = out.initial_domains();
+        Ok(out)
+    }
+
+    //makes either axis wrap around, so the finished grid repeats seamlessly along it. clears the grid like reset
+    pub fn set_boundary(&mut self, horizontal:Boundary, vertical:Boundary)->Result<(), Error>{
+        self.grid.set_boundary(horizontal, vertical);
+        self.topology = self.grid.topology(&self.neighborhood);
+        self.reset()
+    }
+
+    //puts virtual tiles past the edges that don't wrap, like ocean all around the map. clears the grid like reset
+    pub fn set_edges(&mut self, edges:Edges)->Result<(), Error>{
+        self.grid.set_edges(edges);
+        self.reset()
+    }
+
+    pub fn get_grid(&self)->&Grid{
+        &self.grid
+    }
+
+    //the grid's cells and the directions the border constraints look in
+    pub fn topology(&self)->&dyn Topology{
+        self.topology.as_ref()
+    }
+
+    //intersects the borders of every border constraint into one bitmask per tile and direction,
+    //over every direction any of them looks in
+    fn build_adjacency(constraints:&[GridConstraint], layout:&Layout, allowed_states:&[TileType], state_index:&HashMap<TileType, usize>)->(Neighborhood, Option<Arc<[Domain]>>){
+        let n = allowed_states.len();
+        let Some(neighborhood) = Neighborhood::union(constraints.iter().filter_map(|c| c.borders()).map(|b| b.1)) else{
+            return (layout.neighborhood(), None);
+        };
+        let dirs = neighborhood.len();
+        let mut masks = vec![Domain::full(n); n*dirs];
+        for (borders, own) in constraints.iter().filter_map(|c| c.borders()){
+            for (ti, t) in allowed_states.iter().enumerate(){
+                let Some(sets) = borders.get(t) else{
+                    continue;
+                };
+                for (i, allowed) in sets.iter().enumerate(){
+                    //directions without offsets are the same in every neighborhood of the layout
+                    let dir = own.offsets().get(i).and_then(|o| neighborhood.index_of(*o)).unwrap_or(i);
+                    if dir>= dirs{
+                        continue;
+                    }
+                    let mut mask = Domain::empty(n);
+                    for a in allowed{
+                        if let Some(ai) = state_index.get(a){
+                            mask.insert(*ai);
+                        }
+                    }
+                    masks[ti*dirs+dir].intersect_with(&mask);
+                }
+            }
+        }
+        (neighborhood, Some(masks.into()))
+    }
+
+    //candidates of every cell given what is currently in the grid
+    fn initial_domains(&self)->Vec<Domain>{
+        let n = self.allowed_states.len();
+        let mut out = Vec::with_capacity(self.grid.width*self.grid.height);
+        for y in 0..self.grid.height{
+            for x in 0..self.grid.width{
+                let v = *self.grid.get_sq(x, y);
+                if v != -1{
+                    out.push(self.state_index.get(&v).map(|i| Domain::single(n, *i)).unwrap_or(Domain::empty(n)));
+                    continue;
+                }
+                let mut d = Domain::full(n);
+                self.filter_generic(&mut d, x, y);
+                self.filter_edges(&mut d, x, y);
+                out.push(d);
+            }
+        }
+        out
+    }
+
+    //keeps only the candidates the edges allow and that fit next to the virtual tiles past them
+    fn filter_edges(&self, domain:&mut Domain, x:usize, y:usize){
+        for i in domain.clone().iter(){
+            if !self.grid.edge_allows(x, y, self.allowed_states[i]){
+                domain.remove(i);
+            }
+        }
+        let Some(adj) = self.adjacency.as_ref() else{
+            return;
+        };
+        let dirs = self.topology.direction_count();
+        for (i, (dx, dy)) in self.neighborhood.offsets().iter().enumerate(){
+            let Some(outside) = self.grid.edge_tiles(x, y, *dx, *dy) else{
+                continue;
+            };
+            let mut support = Domain::empty(self.allowed_states.len());
+            for t in outside{
+                if let Some(ti) = self.state_index.get(&t){
+                    support.union_with(&adj[ti*dirs+self.topology.inverse(i)]);
+                }
+            }
+            domain.intersect_with(&support);
+        }
+    }
+
+    //propagates from every cell with a value and every cell the constraints or edges already narrowed,
+    //for when the domains were just rebuilt from the grid
+    fn propagate_from_grid(&mut self)->Result<(), Error>{
+        let n = self.allowed_states.len();
+        let start:Vec<usize> = (0..self.domains.len()).filter(|i| self.grid.values[*i] != -1 || self.domains[*i].len()<n).collect();
+        self.propagate_cells(&start)
+    }
+
+    //removes every candidate that fails a constraint which has no precomputed bitmasks
+    fn filter_generic(&self, domain:&mut Domain, x:usize, y:usize){
+        for c in &self.constraints{
+            if c.borders().is_some(){
+                continue;
+            }
+            c.filter_allowed(&self.grid, &self.allowed_states, domain, x, y);
+        }
+    }
+
+    //every tile allowed in direction dir of a cell, given what that cell can still be. None if there are no border constraints
+    fn support(&self, idx:usize, dir:usize)->Option<Domain>{
+        let adj = self.adjacency.as_ref()?;
+        let mut out = Domain::empty(self.allowed_states.len());
+        for w in self.domains[idx].iter(){
+            out.union_with(&adj[w*self.topology.direction_count()+dir]);
+        }
+        Some(out)
+    }
+
+    //same as support but for a cell that holds value_idx
+    fn value_support(&self, value_idx:Option<usize>, dir:usize)->Option<Domain>{
+        let (adj, vi) = self.adjacency.as_ref().zip(value_idx)?;
+        Some(adj[vi*self.topology.direction_count()+dir].clone())
+    }
+
+    //the domain of cell with everything outside support and everything the generic constraints reject removed
+    fn revised_domain(&self, cell:usize, support:Option<&Domain>)->Domain{
+        let mut d = self.domains[cell].clone();
+        if let Some(support) = support{
+            d.intersect_with(support);
+        }
+        let (x, y) = self.grid.position(cell);
+        self.filter_generic(&mut d, x, y);
+        d
+    }
+
+    fn set_domain(&mut self, idx:usize, domain:Domain){
+        if self.domains[idx] == domain{
+            return;
+        }
+        let old = std::mem::replace(&mut self.domains[idx], domain);
+        self.trail.push(TrailEntry::Domain(idx, old));
+    }
+
+    //sets a cell without touching any other cell
+    fn assign(&mut self, cell:usize, value:TileType){
+        self.grid.values[cell] = value;
+        self.trail.push(TrailEntry::Assigned(cell));
+        if let Some(vi) = self.state_index.get(&value){
+            self.set_domain(cell, Domain::single(self.allowed_states.len(), *vi));
+        }
+    }
+
+    //adds a constraint on the grid as a whole and narrows the domains to match it.
+    //if it can't hold with what is already in the grid it isn't added and the solver is left as it was
+    pub fn add_global_constraint(&mut self, constraint:impl GlobalConstraint+'static)->Result<(), Error>{
+        let start = self.trail.len();
+        self.globals.push(Arc::new(constraint));
+        if let Err(e) = self.propagate_cells(&[]){
+            self.undo_trail(start);
+            self.globals.pop();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    //runs arc consistency from the changed cells, then lets the global constraints narrow domains
+    //and propagates whatever they changed, until neither has anything left to do
+    pub fn propagate(&mut self, changed:&[(usize, usize)])->Result<(), Error>{
+        let cells:Vec<usize> = changed.iter().map(|(x, y)| self.grid.index(*x, *y)).collect();
+        self.propagate_cells(&cells)
+    }
+
+    fn propagate_cells(&mut self, changed:&[usize])->Result<(), Error>{
+        self.propagate_local(changed)?;
+        if self.globals.is_empty(){
+            return Ok(());
+        }
+        loop{
+            let mut changed = Vec::new();
+            for g in self.globals.clone(){
+                for (idx, d) in g.narrow(&self.grid, &self.domains, &self.allowed_states)?{
+                    if self.narrow_to(idx, d)?{
+                        changed.push(idx);
+                    }
+                }
+            }
+            if changed.is_empty(){
+                return Ok(());
+            }
+            self.propagate_local(&changed)?;
+        }
+    }
+
+    //replaces the domain of a cell with a narrower one and collapses the cell if only one option is left,
+    //returns true if anything changed
+    fn narrow_to(&mut self, idx:usize, domain:Domain)->Result<bool, Error>{
+        if domain == self.domains[idx]{
+            return Ok(false);
+        }
+        let empty = domain.is_empty();
+        self.set_domain(idx, domain);
+        if empty{
+            let (x, y) = self.grid.position(idx);
+            return Err(Error::Contradiction { x, y });
+        }
+        if self.domains[idx].len() == 1 && self.grid.values[idx] == -1{
+            self.grid.values[idx] = self.allowed_states[self.domains[idx].first().expect("domain has one value")];
+            self.trail.push(TrailEntry::Assigned(idx));
+        }
+        Ok(true)
+    }
+
+    //arc consistency: takes changed cells off a worklist and removes every value of their neighbors that lost its support,
+    //queueing those neighbors in turn until nothing changes. cells left with one option get collapsed along the way.
+    //fails with the first cell left with no options
+    fn propagate_local(&mut self, changed:&[usize])->Result<(), Error>{
+        let mut queued = vec![false; self.domains.len()];
+        let mut queue = VecDeque::new();
+        for cell in changed{
+            if !queued[*cell]{
+                queued[*cell] = true;
+                queue.push_back(*cell);
+            }
+        }
+        while let Some(cell) = queue.pop_front(){
+            queued[cell] = false;
+            for (dir, other) in self.topology.neighbors(cell){
+                let support = self.support(cell, dir);
+                let d = self.revised_domain(other, support.as_ref());
+                if !self.narrow_to(other, d)?{
+                    continue;
+                }
+                if !queued[other]{
+                    queued[other] = true;
+                    queue.push_back(other);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //restarts the random number generator from seed, solving an empty grid with the same seed always gives the same result
+    pub fn set_seed(&mut self, seed:u64){
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    //draws a seed from an outside generator, the run can still be replayed later with get_seed
+    pub fn seed_from_rng(&mut self, rng:&mut impl RngCore){
+        self.set_seed(rng.next_u64());
+    }
+
+    //the seed the solver was last started from, either given or picked at random on creation
+    pub fn get_seed(&self)->u64{
+        self.seed
+    }
+
+    //weights used for entropy, tiles that aren't listed have a weight of 1
+    pub fn set_weights(&mut self, weights:&[(TileType, f64)]){
+        self.weights = weights.iter().copied().collect();
+    }
+
+    //clears the grid and everything the solver remembers about previous attempts, then puts the pins back.
+    //fails if the pins, edges and global constraints can't all hold on an empty grid, which can happen after
+    //the edges or boundary change. the pins are kept either way, so clear_pins starts over without them
+    pub fn reset(&mut self)->Result<(), Error>{
+        self.grid.reset();
+        self.trail.clear();
+        self.decisions.clear();
+        self.domains = self.initial_domains();
+        let pins = self.pins.clone();
+        let result = self.propagate_from_grid().and_then(|_| self.apply_pins(&pins));
+        self.trail.clear();
+        result
+    }
+
+    //fixes cells to the given tiles before solving, undoing any choices made so far first.
+    //the pins are checked against each other and the constraints and propagated, if that fails none of them are kept.
+    //pinned cells are never undone by backtracking and come back after reset
+    pub fn pin(&mut self, pins:&[(usize, usize, TileType)])->Result<(), Error>{
+        for &(x, y, tile) in pins{
+            if x>= self.grid.width || y>= self.grid.height{
+                return Err(Error::OutOfBounds { x, y });
+            }
+            if !self.state_index.contains_key(&tile){
+                return Err(Error::TileOutOfRange(tile));
+            }
+        }
+        if let Some(first) = self.decisions.first(){
+            let trail_len = first.trail_len;
+            self.undo_trail(trail_len);
+            self.decisions.clear();
+        }
+        let start = self.trail.len();
+        if let Err(e) = self.apply_pins(pins){
+            self.undo_trail(start);
+            return Err(e);
+        }
+        //the pins are part of the starting state now, so nothing can roll them back
+        self.trail.clear();
+        self.pins.extend_from_slice(pins);
+        Ok(())
+    }
+
+    //pins every defined cell of a partially filled grid of the same size
+    pub fn pin_grid(&mut self, grid:&Grid)->Result<(), Error>{
+        if grid.width != self.grid.width || grid.height != self.grid.height{
+            return Err(Error::SizeMismatch { expected: self.grid.width*self.grid.height, found: grid.width*grid.height });
+        }
+        let mut pins = Vec::new();
+        for y in 0..grid.height{
+            for x in 0..grid.width{
+                let v = *grid.get_sq(x, y);
+                if v != -1{
+                    pins.push((x, y, v));
+                }
+            }
+        }
+        self.pin(&pins)
+    }
+
+    //clears the cells of the rectangle from (x_start, y_start) up to but not including (x_end, y_end) and solves them again,
+    //see regenerate_mask
+    pub fn regenerate_rect(&mut self, x_start:usize, y_start:usize, x_end:usize, y_end:usize, selection_mode:&SelectionStrategy)->Result<(), Error>{
+        if x_end>self.grid.width || y_end>self.grid.height{
+            return Err(Error::OutOfBounds { x: x_end-1, y: y_end-1 });
+        }
+        let mut mask = vec![false; self.grid.width*self.grid.height];
+        for y in y_start..y_end{
+            for x in x_start..x_end{
+                mask[y*self.grid.width+x] = true;
+            }
+        }
+        self.regenerate_mask(&mask, selection_mode)
+    }
+
+    //clears every cell where mask (indexed y*width+x) is true and solves them again, everything else stays as it is
+    //and the new cells have to fit against it. pinned cells are kept and backtracking never leaves the region.
+    //if the region can't be filled the grid is left as it was
+    pub fn regenerate_mask(&mut self, mask:&[bool], selection_mode:&SelectionStrategy)->Result<(), Error>{
+        if mask.len() != self.grid.width*self.grid.height{
+            return Err(Error::SizeMismatch { expected: self.grid.width*self.grid.height, found: mask.len() });
+        }
+        let old_grid = self.grid.clone();
+        let old_domains = self.domains.clone();
+        for (i, m) in mask.iter().enumerate(){
+            let (x, y) = (i%self.grid.width, i/self.grid.width);
+            if *m && !self.is_pinned(x, y){
+                *(self.grid.get_sqmut(x, y)) = -1;
+            }
+        }
+        self.trail.clear();
+        self.decisions.clear();
+        self.domains = self.initial_domains();
+        //the kept cells are the starting state, so only choices inside the region get backtracked
+        let result = self.propagate_from_grid().and_then(|_|{
+            self.trail.clear();
+            self.collapse_fully_backtracking(selection_mode)
+        });
+        if result.is_err(){
+            self.grid = old_grid;
+            self.domains = old_domains;
+            self.trail.clear();
+            self.decisions.clear();
+        }
+        result
+    }
+
+    //drops every pin and starts over
+    pub fn clear_pins(&mut self)->Result<(), Error>{
+        self.pins.clear();
+        self.reset()
+    }
+
+    pub fn is_pinned(&self, x:usize, y:usize)->bool{
+        self.pins.iter().any(|p| p.0 == x && p.1 == y)
+    }
+
+    fn apply_pins(&mut self, pins:&[(usize, usize, TileType)])->Result<(), Error>{
+        let mut cells = Vec::with_capacity(pins.len());
+        for &(x, y, tile) in pins{
+            let cell = self.grid.index(x, y);
+            let vi = self.state_index[&tile];
+            let current = self.grid.values[cell];
+            if (current != -1 && current != tile) || !self.domains[cell].contains(vi) || !self.check_collapse_allowed(x, y, tile){
+                return Err(Error::Contradiction { x, y });
+            }
+            self.assign(cell, tile);
+            cells.push(cell);
+        }
+        self.propagate_cells(&cells)
+    }
+
+    pub fn is_state_valid(&self)->bool{
+        self.validate().is_ok()
+    }
+
+    //fails with the first cell that breaks a constraint, or the first global constraint that doesn't hold
+    fn validate(&self)->Result<(), Error>{
+        for y in 0..self.grid.height{
+            for x in 0..self.grid.width{
+                if !self.check_collapse_allowed(x, y, *self.grid.get_sq(x,y)){
+                    return Err(Error::Contradiction { x, y });
+                }
+            }
+        }
+        for g in &self.globals{
+            if !g.is_satisfied(&self.grid){
+                return Err(Error::GlobalContradiction(format!("{:?}", g)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn new_from_borders( allowed_states:Arc<[TileType]>,allowed_border:HashMap<i32, Vec<HashSet<i32>>>, height:usize, width:usize)->Self{
+        let mut constraints:Vec<GridConstraint> = Vec::new();
+        constraints.push(GridConstraint::new_from_borders(allowed_border));
+        Self::new_with_constraints(height, width, allowed_states, constraints)
+    }
+
+    pub fn constraints_and_allowed_from_data(data:&[TileType], height:usize, width:usize)->Result<(Borders, Vec<TileType>), Error>{
+        Self::constraints_and_allowed_from_data_with_neighborhood(data, height, width, &Neighborhood::moore())
+    }
+
+    //learns one set of allowed neighbors per direction of neighborhood, data is height rows of width tiles
+    pub fn constraints_and_allowed_from_data_with_neighborhood(data:&[TileType], height:usize, width:usize, neighborhood:&Neighborhood)->Result<(Borders, Vec<TileType>), Error>{
+        let stats = SampleStatistics::from_data(data, height, width, neighborhood)?;
+        Ok((stats.borders(), stats.tiles))
+    }
+
+    //the constraints all have to hold, so more than one gets saved as an all
+    pub fn serialize_constraints(&self)->Result<String, Error>{
+        let value = if self.constraints.len() == 1{
+            self.constraints[0].serialize()?
+        } else{
+            GridConstraint::all(self.constraints.clone()).serialize()?
+        };
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+
+    pub fn write_constraints_to_file(&self, file_name:&str)->Result<(), Error>{
+        std::fs::write(file_name,self.serialize_constraints()?)?;
+        Ok(())
+    }
+
+    //saves the size, tiles, local and global constraints, weights, seed and the grid as it is now
+    pub fn save_setup(&self)->Result<String, Error>{
+        let mut weights:Vec<(TileType, f64)> = self.weights.iter().map(|(t, w)| (*t, *w)).collect();
+        weights.sort_by_key(|i| i.0);
+        let setup = SolverSetup{
+            height:self.grid.height, 
+            width:self.grid.width, 
+            layout:self.grid.layout.clone(),
+            allowed_states:self.allowed_states.to_vec(), 
+            constraints:self.constraints.iter().map(|c| c.serialize()).collect::<Result<_, _>>()?,
+            globals:self.globals.iter().map(|g| Ok(serde_json::json!({g.tag():g.to_json()?}))).collect::<Result<_, Error>>()?,
+            pins:self.pins.clone(),
+            boundary:self.grid.get_boundary(),
+            edges:self.grid.edges.clone(),
+            weights,
+            statistics:self.statistics.as_deref().cloned(),
+            seed:self.seed,
+            grid:self.grid.values.to_vec(),
+        };
+        Ok(serde_json::to_string_pretty(&setup)?)
+    }
+
+    //rebuilds a solver saved with save_setup, constraints are loaded through registry and propagated from the saved grid
+    pub fn load_setup(data:&str, registry:&ConstraintRegistry)->Result<Self, Error>{
+        let setup:SolverSetup = serde_json::from_str(data)?;
+        if setup.grid.len() != setup.height*setup.width{
+            return Err(malformed("grid doesn't match height and width"));
+        }
+        let constraints = setup.constraints.iter().map(|c| registry.load(c)).collect::<Result<_, _>>()?;
+        let mut out = Self::new_with_layout(setup.layout, setup.height, setup.width, setup.allowed_states.into(), constraints)?;
+        out.grid.set_boundary(setup.boundary.0, setup.boundary.1);
+        out.topology = out.grid.topology(&out.neighborhood);
+        out.grid.set_edges(setup.edges);
+        for g in &setup.globals{
+            out.globals.push(registry.load_global(g)?);
+        }
+        out.set_weights(&setup.weights);
+        out.statistics = setup.statistics.map(Arc::new);
+        out.set_seed(setup.seed);
+        for (i, v) in setup.grid.iter().enumerate(){
+            if *v == -1{
+                continue;
+            }
+            if !out.state_index.contains_key(v){
+                return Err(Error::TileOutOfRange(*v));
+            }
+            out.grid.values[i] = *v;
+        }
+        out.domains = out.initial_domains();
+        out.propagate_from_grid()?;
+        out.trail.clear();
+        out.pins = setup.pins;
+        Ok(out)
+    }
+
+    pub fn write_setup_to_file(&self, file_name:&str)->Result<(), Error>{
+        std::fs::write(file_name, self.save_setup()?)?;
+        Ok(())
+    }
 
-    pub fn new(height:usize,width:usize, allowed_states:Arc<[TileType]>)->Self{
-        Self{grid:Grid::new(height, width), constraints:Vec::new(), allowed_states, allowed_neighbors_cache:HashMap::new()}
+    pub fn load_setup_from_file(file_name:&str, registry:&ConstraintRegistry)->Result<Self, Error>{
+        Self::load_setup(&std::fs::read_to_string(file_name)?, registry)
+    }
+    //learns the constraints, and how often every tile showed up as its weight, from a height x width example grid
+    pub fn new_from_data(data:&[TileType], height:usize, width:usize)->Result<Self, Error>{
+        Self::new_from_data_with_neighborhood(data, height, width, Neighborhood::moore())
     }
 
-    pub fn new_with_constraints(height:usize, width:usize,allowed_states:Arc<[TileType]>, constraints:Vec<GridConstraint>)->Self{
-        Self{grid:Grid::new(height, width), constraints, allowed_states,allowed_neighbors_cache:HashMap::new()} 
+    //like new_from_data but only learns and checks the directions in neighborhood
+    pub fn new_from_data_with_neighborhood(data:&[TileType], height:usize, width:usize, neighborhood:Neighborhood)->Result<Self, Error>{
+        Self::new_from_statistics(SampleStatistics::from_data(data, height, width, &neighborhood)?, height, width)
     }
 
-    pub fn is_state_valid(&self)->bool{
-        for y in 0..self.grid.height{
-            for x in 0..self.grid.width{
-                if !self.constraints[0].check_constraint(&self.grid,*self.grid.get_sq(x,y), x,y){
-                    return false
-                }
-            }
-        }
-        true
+    //like new_from_data but also learns from the sample turned and mirrored by each of symmetries, see SampleStatistics::augmented
+    pub fn new_from_data_with_symmetry(data:&[TileType], height:usize, width:usize, neighborhood:Neighborhood, symmetries:&[Symmetry], variants:&TileVariants)->Result<Self, Error>{
+        let stats = SampleStatistics::from_data(data, height, width, &neighborhood)?.augmented(symmetries, variants)?;
+        Self::new_from_statistics(stats, height, width)
     }
 
-    pub fn new_from_borders( allowed_states:Arc<[TileType]>,allowed_border:HashMap<i32, Vec<HashSet<i32>>>, height:usize, width:usize)->Self{
-        let mut constraints:Vec<GridConstraint> = Vec::new();
-        constraints.push(GridConstraint::new_from_borders(allowed_border));
-        Self{grid:Grid::new(height, width), constraints, allowed_states:allowed_states.into(),allowed_neighbors_cache:HashMap::new()}
-    }
-
-    pub fn constraints_and_allowed_from_data(data:&[TileType], height:usize, width:usize)->(HashMap<i32, Vec<HashSet<i32>>>, Vec<TileType>){
-        let mut used:HashSet<TileType> = HashSet::new();
-        let mut allowed_states:Vec<TileType> = Vec::new();
-        for i in data{
-            if !used.contains(i){
-                used.insert(*i);
-                allowed_states.push(*i);
-            }
-        }
-        let mut allowed_border = HashMap::new();
-        for i in &allowed_states{
-            let mut tmp_vec = Vec::new();
-            for j in 0..8{
-                let mut states = HashSet::new();
-                let (dx, dy) = OFFSETS[j];
-                for y in 0..height{
-                    for x in 0..width{
-                        if data[y*height+x] != *i{
-                            continue;
-                        }
-                        if x as isize+dx<0 || y as isize+dy<0{
-                            continue;
-                        }
-                        if (x as isize+dx) as usize>=width || (y as isize+dy) as usize>=height{
-                            continue;
-                        }
-                        states.insert(data[((y as isize+dy) as usize)*height+(x as isize+dx) as usize]);
-                    }
-                }
-                tmp_vec.push(states);
-            }
-            allowed_border.insert(*i,tmp_vec);
-        }
-        (allowed_border, allowed_states)
+    //learns from a sample grid laid out any way, see SampleStatistics::from_grid, and solves a grid of the same size and layout.
+    //on a hex grid neighborhood is in axial offsets, usually Neighborhood::hex
+    pub fn new_from_sample(sample:&Grid, neighborhood:Neighborhood)->Result<Self, Error>{
+        Self::from_statistics(SampleStatistics::from_grid(sample, &neighborhood), sample.layout.clone(), sample.height, sample.width)
     }
 
-    pub fn serialize_constraints(&self)->Result<String, Box<dyn std::error::Error>>{
-        (self.constraints[0].serialize_fn)()
+    //a solver whose borders, tiles and weights come from stats, fails if the neighbor counts don't match its neighborhood
+    pub fn new_from_statistics(stats:SampleStatistics, height:usize, width:usize)->Result<Self, Error>{
+        Self::from_statistics(stats, Layout::Square, height, width)
     }
 
-    pub fn write_constraints_to_file(&self, file_name:&str)->Result<(), Box<dyn std::error::Error>>{
-        std::fs::write(file_name,(self.constraints[0].serialize_fn)()?)?;
-        Ok(())
+    fn from_statistics(stats:SampleStatistics, layout:Layout, height:usize, width:usize)->Result<Self, Error>{
+        let constraints = vec![GridConstraint::new_from_borders_with_neighborhood(stats.borders(), stats.neighborhood.clone())?];
+        let mut out = Self::new_with_layout(layout, height, width, stats.tiles.clone().into(), constraints)?;
+        out.set_weights(&stats.weights());
+        out.statistics = Some(Arc::new(stats));
+        Ok(out)
     }
-    pub fn new_from_data(data:&[TileType], height:usize, width:usize)->Self{
-        let (allowed_border, allowed_states) = Self::constraints_and_allowed_from_data(data, height, width);
-        Self::new_from_borders(allowed_states.into(), allowed_border, height, width)
+
+    pub fn get_statistics(&self)->Option<&SampleStatistics>{
+        self.statistics.as_deref()
     }
 
-    //sets the value at the location to the requested one, clears the cache of the value and all it's neighbors
+    //sets the value at the location to the requested one, the rest of the grid only finds out once propagate is called
     pub unsafe fn collapse_unchecked(&mut self, x:usize, y:usize, value:TileType){
-        *(self.grid.get_sqmut(x, y)) = value;
-        //self.allowed_neighbors_cache.clear();
-    }
-
-    //returns true on error
-    pub unsafe fn collapse_unchecked_recursive(&mut self, x:usize, y:usize, value:TileType)->bool{
-        *(self.grid.get_sqmut(x, y)) = value;
-        let ix = x as isize;
-        let iy = y as isize;
-        self.allowed_neighbors_cache.remove(&(x,y));
-        for i in 0..8{
-            let dx = OFFSETS[i].0;
-            let dy = OFFSETS[i].1;
-            let sx = ix+dx;
-            let sy = iy+dy;
-            if sx< 0 || sy<0 || sx>= self.grid.width as isize || sy >= self.grid.height as isize{
-                continue;
-            }
-            let vx = sx as usize;
-            let vy = sy as usize;
-            self.allowed_neighbors_cache.remove(&(vx,vy));
-            if *(self.grid.get_sq(vx, vy)) != -1{
-                continue;
-            }
-            let allowed = self.allowed_states_at(vx, vy);
-            if allowed.len() == 1{
-                let result = self.collapse_unchecked_recursive(vx, vy, allowed[0]);
-                if result{
-                    return true;
-                }
-            } else if allowed.is_empty(){
-                return true;
-            }
-        } 
-        false
+        self.assign(self.grid.index(x, y), value);
+    }
+
+    pub unsafe fn collapse_unchecked_recursive(&mut self, x:usize, y:usize, value:TileType)->Result<(), Error>{
+        let cell = self.grid.index(x, y);
+        self.assign(cell, value);
+        self.propagate_cells(&[cell])
     }
     pub fn check_collapse_allowed(&self, x:usize, y:usize, test_value:TileType)->bool{
+        if test_value != -1 && !self.grid.edge_allows(x, y, test_value){
+            return false;
+        }
         for i in &self.constraints{
             if !i.check_constraint(&self.grid, test_value, x, y){
                 return false;
@@ -284,95 +1462,167 @@ impl ConstraintSolver{
         true
     }
 
-    pub fn allowed_states_at(&mut self,x:usize, y:usize)->Vec<TileType>{
-        if let Some(v) = self.allowed_neighbors_cache.get(&(x,y)){
-            return v.clone();
-        }
-        let mut out = vec![];
-        for i in &self.constraints{
-            let mut tmp = i.get_allowed_states(&self.grid, &self.allowed_states, x, y);
-            out.append(&mut tmp);
-        }
-        self.allowed_neighbors_cache.insert((x,y), out.clone());
-        out
+    pub fn allowed_states_at(&self,x:usize, y:usize)->Vec<TileType>{
+        self.domains[self.grid.index(x, y)].iter().map(|i| self.allowed_states[i]).collect()
     }
 
-    //returns true if it reached an unreachable state
-    pub fn collapse_all_determined(&mut self)->bool{
-        let previous = self.grid.clone();
-        let mut reset = true;
-        while reset{
-            reset = false;
-            for x in 0..self.grid.width{
-                for y in 0..self.grid.height{
-                    if *(self.grid.get_sq(x, y)) != -1{
-                        continue;
-                    }
-                    let al = self.allowed_states_at(x, y);
-                    if al.is_empty(){
-                        self.grid = previous;
-                        return true;
-                    }
-                    if al.len() == 1{
-                        unsafe{self.collapse_unchecked_recursive(x, y, al[0]);}
-                        reset = true;
-                    }
-                }
+    fn weight_of(&self, tile:TileType, selection_mode:&SelectionStrategy)->f64{
+        match selection_mode{
+            SelectionStrategy::FromDistribution { distribution }=>{
+                distribution.iter().find(|i| i.0 == tile).map(|i| i.1).unwrap_or(0.0)
+            }
+            _=>{
+                *self.weights.get(&tile).unwrap_or(&1.0)
             }
         }
-        false
     }
 
-    //returns Ok(true) if the collapse was allowed Ok(false) if collapsing reached an unreachable state returns an error
-    pub fn attempt_collapse_to_value(&mut self,x:usize, y:usize, value:TileType)->Result<bool,()>{
-        if self.check_collapse_allowed(x, y, value){
-         
-            let result =    unsafe{
-                self.collapse_unchecked_recursive(x,y,value)
-            };
-            if result {
-                Err(())
-            } else if self.is_state_valid(){
-                Ok(true)
+    fn entropy_of(&self, states:&[TileType], selection_mode:&SelectionStrategy)->f64{
+        let weights:Vec<f64> = states.iter().map(|i| self.weight_of(*i, selection_mode)).collect();
+        utils::shannon_entropy(&weights)
+    }
+
+    //shannon entropy of the weighted candidates at a location
+    pub fn entropy_at(&self, x:usize, y:usize, selection_mode:&SelectionStrategy)->f64{
+        let allowed = self.allowed_states_at(x, y);
+        self.entropy_of(&allowed, selection_mode)
+    }
+
+    //total entropy left in the undefined neighbors of a location if it were set to value, None if that would leave a neighbor with no options
+    fn neighbor_entropy_after(&mut self, x:usize, y:usize, value:TileType, selection_mode:&SelectionStrategy)->Option<f64>{
+        let cell = self.grid.index(x, y);
+        let previous = self.grid.values[cell];
+        self.grid.values[cell] = value;
+        let value_idx = self.state_index.get(&value).copied();
+        let mut total = Some(0.0);
+        for (dir, other) in self.topology.neighbors(cell){
+            if self.grid.values[other] != -1{
+                continue;
             }
-            else{
-                Err(())
+            let support = self.value_support(value_idx, dir);
+            let allowed:Vec<TileType> = self.revised_domain(other, support.as_ref()).iter().map(|i| self.allowed_states[i]).collect();
+            if allowed.is_empty(){
+                total = None;
+                break;
             }
+            total = total.map(|t| t+self.entropy_of(&allowed, selection_mode));
+        }
+        self.grid.values[cell] = previous;
+        total
+    }
 
+    //picks the candidate that leaves the neighbors with the least (or most) entropy, ties are broken randomly
+    fn select_by_entropy(&mut self, x:usize, y:usize, allowed_states:&[TileType], selection_mode:&SelectionStrategy, maximize:bool)->TileType{
+        let mut scored = Vec::new();
+        for i in allowed_states{
+            if let Some(e) = self.neighbor_entropy_after(x, y, *i, selection_mode){
+                scored.push((*i, e));
+            }
+        }
+        if scored.is_empty(){
+            //every option dead ends, let the caller find out about the contradiction
+            return allowed_states[self.rng.next_u64() as usize % allowed_states.len()];
+        }
+        let best = if maximize{
+            scored.iter().map(|i| i.1).fold(f64::NEG_INFINITY, f64::max)
         } else{
-            Ok(false)
+            scored.iter().map(|i| i.1).fold(f64::INFINITY, f64::min)
+        };
+        let ties:Vec<TileType> = scored.iter().filter(|i| (i.1-best).abs()<1e-9).map(|i| i.0).collect();
+        ties[self.rng.next_u64() as usize % ties.len()]
+    }
+
+    //collapses every cell that only has one option left, nothing is changed if that leads to a contradiction
+    pub fn collapse_all_determined(&mut self)->Result<(), Error>{
+        let previous = self.trail.len();
+        let all:Vec<usize> = (0..self.domains.len()).collect();
+        let result = self.propagate_cells(&all);
+        if result.is_err(){
+            self.undo_trail(previous);
+        }
+        result
+    }
+
+    //returns Ok(true) if the collapse was allowed, Ok(false) if the constraints don't allow value there, and an error if collapsing reached an unreachable state
+    pub fn attempt_collapse_to_value(&mut self,x:usize, y:usize, value:TileType)->Result<bool, Error>{
+        if !self.state_index.contains_key(&value){
+            return Err(Error::TileOutOfRange(value));
+        }
+        if !self.check_collapse_allowed(x, y, value){
+            return Ok(false);
+        }
+        unsafe{
+            self.collapse_unchecked_recursive(x,y,value)?;
         }
+        self.validate()?;
+        Ok(true)
     }
 
-    //returns Ok(true) if the collapse was allowed Ok(false) reached an unreachable state returns an error
-    pub fn collapse_location(&mut self, x:usize, y:usize, selction_mode:&SelectionStrategy)->Result<bool, ()>{
-        let mut rand= thread_rng();
+    //picks a value for the location and propagates it, fails if the location or one it affects has no options left
+    pub fn collapse_location(&mut self, x:usize, y:usize, selction_mode:&SelectionStrategy)->Result<(), Error>{
         let allowed_states = self.allowed_states_at(x, y).into_boxed_slice();
         if allowed_states.len() <1{
-            return Err(());
+            return Err(Error::Contradiction { x, y });
         }
         let state = match selction_mode{
             SelectionStrategy::PurelyRandom=>{
-                let i = rand.next_u64() as usize % allowed_states.len();
+                let i = self.rng.next_u64() as usize % allowed_states.len();
                 allowed_states[i]
             }
             SelectionStrategy::FromDistribution {distribution }=>{
-                 utils::slice_rand_select(distribution.as_ref())
+                //only the tiles still allowed here take part, their weights get renormalized by slice_rand_select
+                let candidates:Vec<(TileType, f64)> = distribution.iter().filter(|i| allowed_states.contains(&i.0)).copied().collect();
+                utils::slice_rand_select(&candidates, &mut self.rng).ok_or(Error::Contradiction { x, y })?
             }
             SelectionStrategy::MaximizeEntropy=>{
-                todo!()
+                self.select_by_entropy(x, y, &allowed_states, selction_mode, true)
             }
             SelectionStrategy::MinimizeEntropy=>{
-                todo!()
+                self.select_by_entropy(x, y, &allowed_states, selction_mode, false)
+            }
+            SelectionStrategy::FromSample=>{
+                let candidates:Vec<(TileType, f64)> = allowed_states.iter().map(|t|{
+                    let weight = match &self.statistics{
+                        Some(stats)=> stats.likelihood(&self.grid, *t, x, y),
+                        None=> self.weight_of(*t, selction_mode),
+                    };
+                    (*t, weight)
+                }).collect();
+                utils::slice_rand_select(&candidates, &mut self.rng).ok_or(Error::Contradiction { x, y })?
             }
         };
-        let result = unsafe {
+        self.decisions.push(Decision{cell:self.grid.index(x, y), value:state, trail_len:self.trail.len()});
+        unsafe {
             self.collapse_unchecked_recursive(x, y, state)
+        }
+    }
+
+    //undoes the most recent decision and everything it caused, then removes the value it picked from that cell's candidates
+    //returns false if there was no decision left to undo
+    pub fn backtrack(&mut self)->bool{
+        let Some(decision) = self.decisions.pop() else{
+            return false;
         };
-        if result{
-            return Err(());
+        self.undo_trail(decision.trail_len);
+        if let Some(vi) = self.state_index.get(&decision.value){
+            let mut d = self.domains[decision.cell].clone();
+            d.remove(*vi);
+            self.set_domain(decision.cell, d);
+        }
+        true
+    }
+
+    fn undo_trail(&mut self, trail_len:usize){
+        while self.trail.len()>trail_len{
+            match self.trail.pop().expect("trail is longer than trail_len"){
+                TrailEntry::Assigned(cell)=>{
+                    self.grid.values[cell] = -1;
+                }
+                TrailEntry::Domain(idx, domain)=>{
+                    self.domains[idx] = domain;
+                }
+            }
         }
-        Ok(true)
     }
 
     pub fn contains_undefined(&self)->bool{
@@ -394,41 +1644,64 @@ impl ConstraintSolver{
         count
     }
 
-    pub fn collapse_lowest_entropy(&mut self, selection_mode:&SelectionStrategy)->Result<bool, ()>{
-        let mut idxs:Vec<((usize,usize), usize)> = Vec::new();
+    //picks the undefined cell with the lowest entropy, or the highest for MaximizeEntropy, and collapses it
+    //returns Ok(false) if there was nothing left to collapse
+    pub fn collapse_lowest_entropy(&mut self, selection_mode:&SelectionStrategy)->Result<bool, Error>{
+        let mut idxs:Vec<((usize,usize), f64)> = Vec::new();
         for y in 0..self.grid.height{
             for x in 0..self.grid.width{
-                if *(self.grid.get_sq(x,y)) == -1{
-                    idxs.push(((x,y), self.allowed_states_at(x, y).len()));
+                if *(self.grid.get_sq(x,y)) != -1{
+                    continue;
+                }
+                let allowed = self.allowed_states_at(x, y);
+                //cells with no options left have to come first so contradictions get noticed
+                if allowed.is_empty(){
+                    return Err(Error::Contradiction { x, y });
                 }
+                idxs.push(((x,y), self.entropy_of(&allowed, selection_mode)));
             }
         }
-        idxs.sort_unstable_by(|a,b|{
-            if a<b{std::cmp::Ordering::Greater} else{ std::cmp::Ordering::Less}
-            }
-          );
-        let x = idxs[0].0.0;
-        let y = idxs[0].0.1;
-        self.collapse_location(x, y, selection_mode)
+        if idxs.is_empty(){
+            return Ok(false);
+        }
+        let best = match selection_mode{
+            SelectionStrategy::MaximizeEntropy=> idxs.iter().map(|i| i.1).fold(f64::NEG_INFINITY, f64::max),
+            _=> idxs.iter().map(|i| i.1).fold(f64::INFINITY, f64::min),
+        };
+        let ties:Vec<(usize,usize)> = idxs.iter().filter(|i| (i.1-best).abs()<1e-9).map(|i| i.0).collect();
+        let (x, y) = ties[self.rng.next_u64() as usize % ties.len()];
+        self.collapse_location(x, y, selection_mode)?;
+        Ok(true)
     }
 
-    pub fn collapse_fully(&mut self, selection_mode:&SelectionStrategy)->bool{
+    pub fn collapse_fully(&mut self, selection_mode:&SelectionStrategy)->Result<(), Error>{
         let mut ud_count = self.undefined_count();
         while ud_count>0{
             //println!("ud_count:{ud_count}");
-            let r = self.collapse_lowest_entropy(selection_mode);
-            if r.is_err(){
-                return false;
-            }
-            if r.is_ok() && !r.unwrap() {
-                return false;
-            }
+            self.collapse_lowest_entropy(selection_mode)?;
             ud_count = self.undefined_count();
         }
-        if !self.is_state_valid(){
-            return false;
+        self.validate()
+    }
+
+    //like collapse_fully but on a contradiction it only undoes the most recent choices instead of giving up,
+    //fails with Unsatisfiable only once every choice has been tried
+    pub fn collapse_fully_backtracking(&mut self, selection_mode:&SelectionStrategy)->Result<(), Error>{
+        loop{
+            if self.undefined_count() == 0{
+                if self.is_state_valid(){
+                    return Ok(());
+                }
+                if !self.backtrack(){
+                    return Err(Error::Unsatisfiable);
+                }
+                continue;
+            }
+            let r = self.collapse_lowest_entropy(selection_mode);
+            if r.is_err() && !self.backtrack(){
+                return Err(Error::Unsatisfiable);
+            }
         }
-        true
     }
 }
 
@@ -444,23 +1717,23 @@ pub fn load_constraints(data:&str)-> Result<HashMap<i32, Vec<HashSet<i32>>>, ser
 }
 
 #[allow(unused)]
-pub fn save_constraints_to_file(constraints:HashMap<i32, Vec<HashSet<i32>>>, file_name:&str)->Result<(), serde_json::Error>{
+pub fn save_constraints_to_file(constraints:HashMap<i32, Vec<HashSet<i32>>>, file_name:&str)->Result<(), Error>{
     let str = save_constraints(constraints)?;
-    std::fs::write(file_name, &str);
+    std::fs::write(file_name, &str)?;
     Ok(())
 }
 
 #[allow(unused)]
-pub fn load_constraints_from_file(file_name:&str)->Result<HashMap<i32, Vec<HashSet<i32>>>, Box<dyn std::error::Error>>{
-    let str = std::fs::read_to_string("")?;
+pub fn load_constraints_from_file(file_name:&str)->Result<HashMap<i32, Vec<HashSet<i32>>>, Error>{
+    let str = std::fs::read_to_string(file_name)?;
     Ok(load_constraints(&str)?)
 }   
 
 #[test]
 fn test_offsets(){
     for i in 0..8{
-        let (x0,y0) = OFFSETS[i];
-        let (x1, y1) = OFFSETS[MINUS_INDICES[i]];
+        let (x0,y0) = utils::OFFSETS[i];
+        let (x1, y1) = utils::OFFSETS[utils::MINUS_INDICES[i]];
         assert!(x1 == -x0);
         assert!(y1 == -y0);
     }
@@ -477,15 +1750,481 @@ fn test_initial_state_is_valid(){
         }
     }
     let mut solve=
-     ConstraintSolver::new_from_data(&data, height, width);
+     ConstraintSolver::new_from_data(&data, height, width).unwrap();
      for i in 0..height*width{     
         solve.grid.values[i] = data[i];
      }
+     solve.domains = solve.initial_domains();
      eprintln!("{:#?}", solve.constraints);
      eprintln!("{}", solve.grid.to_str());
      assert!(solve.is_state_valid());
 }
 
+#[test]
+fn test_backtrack_undoes_decision(){
+    let height =6;
+    let width = 6;
+    let mut data =Vec::new();
+    for i in 0..height{
+        for j in 0..width{
+            data.push(((i+j)%3) as TileType);
+        }
+    }
+    let mut solve = ConstraintSolver::new_from_data(&data, height, width).unwrap();
+    assert!(solve.collapse_location(2, 2, &SelectionStrategy::PurelyRandom).is_ok());
+    let picked = *solve.grid.get_sq(2, 2);
+    assert!(solve.backtrack());
+    assert_eq!(solve.undefined_count(), height*width);
+    assert!(!solve.allowed_states_at(2, 2).contains(&picked));
+    assert!(!solve.backtrack());
+}
+
+#[test]
+fn test_collapse_fully_backtracking(){
+    let height =12;
+    let width = 12;
+    let mut data =Vec::new();
+    for i in 0..height{
+        for j in 0..width{
+            data.push(((i+2*j)%4) as TileType);
+        }
+    }
+    let mut solve = ConstraintSolver::new_from_data(&data, height, width).unwrap();
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    assert!(!solve.contains_undefined());
+    assert!(solve.is_state_valid());
+}
+
+#[test]
+fn test_entropy_strategies(){
+    let height =10;
+    let width = 10;
+    let mut data =Vec::new();
+    for i in 0..height{
+        for j in 0..width{
+            data.push(((i+j)%3) as TileType);
+        }
+    }
+    for strategy in [SelectionStrategy::MinimizeEntropy, SelectionStrategy::MaximizeEntropy]{
+        let mut solve = ConstraintSolver::new_from_data(&data, height, width).unwrap();
+        solve.set_weights(&[(0, 2.0), (1, 1.0), (2, 1.0)]);
+        assert!(solve.collapse_fully_backtracking(&strategy).is_ok());
+        assert!(solve.is_state_valid());
+    }
+}
+
+#[test]
+fn test_domains_follow_borders(){
+    let mut borders = HashMap::new();
+    //0 may only sit next to 1, 1 may sit next to anything
+    borders.insert(0, vec![HashSet::from([1]); 8]);
+    borders.insert(1, vec![HashSet::from([0, 1]); 8]);
+    let mut solve = ConstraintSolver::new_from_borders(Arc::new([0, 1]), borders, 3, 3);
+    assert_eq!(solve.allowed_states_at(1, 1).len(), 2);
+    unsafe{solve.collapse_unchecked(1, 1, 0);}
+    assert!(solve.propagate(&[(1, 1)]).is_ok());
+    for (dx, dy) in utils::OFFSETS{
+        let x = (1+dx) as usize;
+        let y = (1+dy) as usize;
+        assert_eq!(solve.allowed_states_at(x, y), vec![1]);
+    }
+}
+
+#[test]
+fn test_propagate_reaches_far_cells(){
+    let mut borders = HashMap::new();
+    //a checkerboard: once one cell is known every other cell is too
+    borders.insert(0, vec![HashSet::from([0]), HashSet::from([1]), HashSet::from([0]), HashSet::from([1]), HashSet::from([1]), HashSet::from([0]), HashSet::from([1]), HashSet::from([0])]);
+    borders.insert(1, vec![HashSet::from([1]), HashSet::from([0]), HashSet::from([1]), HashSet::from([0]), HashSet::from([0]), HashSet::from([1]), HashSet::from([0]), HashSet::from([1])]);
+    let mut solve = ConstraintSolver::new_from_borders(Arc::new([0, 1]), borders.clone(), 8, 8);
+    assert!(unsafe{solve.collapse_unchecked_recursive(0, 0, 0)}.is_ok());
+    assert!(!solve.contains_undefined());
+    assert!(solve.is_state_valid());
+    assert_eq!(*solve.grid.get_sq(7, 7), 0);
+    assert_eq!(*solve.grid.get_sq(6, 7), 1);
+    //an odd distance apart, so these two can't both be 0
+    let mut solve = ConstraintSolver::new_from_borders(Arc::new([0, 1]), borders, 8, 8);
+    unsafe{solve.collapse_unchecked(0, 0, 0);}
+    unsafe{solve.collapse_unchecked(7, 0, 0);}
+    assert!(matches!(solve.propagate(&[(0, 0), (7, 0)]), Err(Error::Contradiction { .. })));
+}
+
+#[test]
+fn test_same_seed_same_result(){
+    let height =12;
+    let width = 12;
+    let mut data =Vec::new();
+    for i in 0..height{
+        for j in 0..width{
+            data.push(((i*j+i)%4) as TileType);
+        }
+    }
+    let mut results = Vec::new();
+    for strategy in [SelectionStrategy::PurelyRandom, SelectionStrategy::MinimizeEntropy]{
+        for _ in 0..2{
+            let mut solve = ConstraintSolver::new_from_data(&data, height, width).unwrap();
+            solve.set_seed(1234);
+            assert!(solve.collapse_fully_backtracking(&strategy).is_ok());
+            results.push(solve.grid.to_str());
+        }
+    }
+    assert_eq!(results[0], results[1]);
+    assert_eq!(results[2], results[3]);
+}
+
+#[test]
+fn test_errors_tell_cause_apart(){
+    let mut borders = HashMap::new();
+    borders.insert(0, vec![HashSet::from([1]); 8]);
+    borders.insert(1, vec![HashSet::from([0]); 8]);
+    //diagonal neighbors of a 0 would both have to be 1 while touching each other, so no 3x3 grid works
+    let mut solve = ConstraintSolver::new_from_borders(Arc::new([0, 1]), borders, 3, 3);
+    assert!(matches!(solve.attempt_collapse_to_value(0, 0, 7), Err(Error::TileOutOfRange(7))));
+    assert!(matches!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom), Err(Error::Unsatisfiable)));
+}
+
+#[test]
+fn test_sparse_tile_types(){
+    //tiles don't have to be numbered from 0 without gaps
+    let data = [0, 5, 0, 5, 0, 5, 0, 5, 0];
+    let mut solve = ConstraintSolver::new_from_data(&data, 3, 3).unwrap().with_size(4, 4).unwrap();
+    solve.set_seed(2);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    assert!(solve.is_state_valid());
+    //a tile that has no borders of its own doesn't rule anything out next to it
+    let rule = GridConstraint::new_from_borders(HashMap::from([(0, vec![HashSet::from([0]); 8])]));
+    let mut grid = Grid::new(2, 2);
+    *grid.get_sqmut(0, 0) = 5;
+    assert!(rule.check_constraint(&grid, 0, 1, 1));
+    *grid.get_sqmut(0, 0) = 0;
+    assert!(!rule.check_constraint(&grid, 5, 1, 1));
+}
+
+#[test]
+fn test_load_sparse_borders(){
+    //saved borders only list the tiles they're about, 3 shows up next to 7 without having borders of its own
+    let rule = ConstraintRegistry::new().load_str(r#"{"borders":{"neighborhood":{"offsets":[[0,-1],[-1,0],[1,0],[0,1]],"opposites":[3,2,1,0]},"borders":{"0":[[0,7],[0,7],[0,7],[0,7]],"7":[[0,3],[0,3],[0,3],[0,3]]}}}"#).unwrap();
+    let mut solve = ConstraintSolver::new_with_constraints(4, 4, Arc::new([0, 3, 7]), vec![rule]);
+    solve.set_seed(5);
+    solve.pin(&[(1, 1, 3)]).unwrap();
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    assert!(solve.is_state_valid());
+}
+
+#[test]
+fn test_distribution_respects_allowed_states(){
+    let height =10;
+    let width = 10;
+    let mut data =Vec::new();
+    for i in 0..height{
+        for j in 0..width{
+            data.push(((i+j)%3) as TileType);
+        }
+    }
+    let distribution:Box<[(TileType, f64)]> = Box::new([(0, 5.0), (1, 1.0), (2, 1.0), (9, 100.0)]);
+    let mut solve = ConstraintSolver::new_from_data(&data, height, width).unwrap();
+    solve.set_seed(3);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::FromDistribution { distribution }).is_ok());
+    assert!(solve.is_state_valid());
+    //a tile with no weight is never picked, so a cell that can only be that tile is a contradiction
+    let distribution:Box<[(TileType, f64)]> = Box::new([(0, 0.0), (1, 1.0), (2, -1.0)]);
+    let mut solve = ConstraintSolver::new_from_data(&data, height, width).unwrap();
+    unsafe{solve.collapse_unchecked(0, 0, 1);}
+    solve.propagate(&[(0, 0)]).unwrap();
+    assert!(matches!(solve.collapse_location(1, 0, &SelectionStrategy::FromDistribution { distribution }), Err(Error::Contradiction { x:1, y:0 })));
+}
+
+//forbids (or with only set, requires) a single tile, for the tests below
+#[cfg(test)]
+#[derive(Debug)]
+struct TestTileRule{
+    tile:TileType,
+    only:bool,
+}
+
+#[cfg(test)]
+impl Constraint for TestTileRule{
+    fn tag(&self)->&'static str{
+        "test_tile_rule"
+    }
+
+    fn check(&self, _grid:&Grid, tile_type:TileType, _x:usize, _y:usize)->bool{
+        (tile_type == self.tile) == self.only
+    }
+
+    fn decided(&self, _grid:&Grid, _x:usize, _y:usize)->bool{
+        true
+    }
+
+    fn to_json(&self)->Result<serde_json::Value, Error>{
+        Ok(serde_json::json!({"tile":self.tile, "only":self.only}))
+    }
+}
+
+#[cfg(test)]
+fn test_registry()->ConstraintRegistry{
+    let mut registry = ConstraintRegistry::new();
+    registry.register("test_tile_rule", |v, _|{
+        Ok(GridConstraint::new(TestTileRule{tile:serde_json::from_value(v["tile"].clone())?, only:serde_json::from_value(v["only"].clone())?}))
+    });
+    registry
+}
+
+#[test]
+fn test_combined_constraints(){
+    let height =8;
+    let width = 8;
+    let mut data =Vec::new();
+    for i in 0..height{
+        for j in 0..width{
+            data.push(((i+j)%3) as TileType);
+        }
+    }
+    let (borders, allowed) = ConstraintSolver::constraints_and_allowed_from_data(&data, height, width).unwrap();
+    let only_zero = GridConstraint::new(TestTileRule{tile:0, only:true});
+    //the top left cell can't hold a 0 and (2, 2) has to be a 0 or a 2, everywhere else anything the sample allows
+    let top_left = GridConstraint::in_region(0, 0, 1, 1, GridConstraint::not(only_zero.clone()));
+    let only_two = GridConstraint::new(TestTileRule{tile:2, only:true});
+    let rule = GridConstraint::in_region(2, 2, 3, 3, GridConstraint::any(vec![only_zero, only_two]));
+    let constraints = vec![GridConstraint::new_from_borders(borders), top_left, rule];
+    let mut solve = ConstraintSolver::new_with_constraints(height, width, allowed.into(), constraints);
+    //pruned up front, long before the neighbors are known
+    assert_eq!(solve.allowed_states_at(0, 0), vec![1, 2]);
+    assert_eq!(solve.allowed_states_at(2, 2), vec![0, 2]);
+    assert!(solve.allowed_states_at(5, 5).contains(&0) && solve.allowed_states_at(5, 5).contains(&1));
+    solve.set_seed(11);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    assert_ne!(*solve.grid.get_sq(0, 0), 0);
+    assert_ne!(*solve.grid.get_sq(2, 2), 1);
+    let json:serde_json::Value = serde_json::from_str(&solve.serialize_constraints().unwrap()).unwrap();
+    assert_eq!(json["all"].as_array().unwrap().len(), 3);
+    assert_eq!(json["all"][1]["in_region"]["x_end"], 1);
+    assert_eq!(json["all"][1]["in_region"]["constraint"]["not"]["test_tile_rule"]["only"], true);
+    assert_eq!(json["all"][2]["in_region"]["constraint"]["any"][1]["test_tile_rule"]["tile"], 2);
+    let when = GridConstraint::when_tile(1, GridConstraint::all(vec![]));
+    assert_eq!(when.serialize().unwrap(), serde_json::json!({"when_tile":{"tile":1, "constraint":{"all":[]}}}));
+}
+
+#[test]
+fn test_setup_round_trip(){
+    let height =8;
+    let width = 8;
+    let mut data =Vec::new();
+    for i in 0..height{
+        for j in 0..width{
+            data.push(((i*j+j)%4) as TileType);
+        }
+    }
+    let (borders, allowed) = ConstraintSolver::constraints_and_allowed_from_data(&data, height, width).unwrap();
+    let constraints = vec![GridConstraint::new_from_borders(borders), GridConstraint::when_tile(3, GridConstraint::in_region(0, 0, 4, 8, GridConstraint::new(TestTileRule{tile:3, only:false})))];
+    let mut solve = ConstraintSolver::new_with_constraints(height, width, allowed.into(), constraints);
+    solve.set_weights(&[(0, 0.5), (2, 3.0)]);
+    solve.set_seed(99);
+    let saved = solve.save_setup().unwrap();
+    assert!(matches!(ConstraintSolver::load_setup(&saved, &ConstraintRegistry::new()), Err(Error::UnknownConstraint(_))));
+    let mut loaded = ConstraintSolver::load_setup(&saved, &test_registry()).unwrap();
+    assert_eq!(loaded.get_seed(), 99);
+    assert_eq!(format!("{:?}", loaded.constraints[1]), format!("{:?}", solve.constraints[1]));
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::MinimizeEntropy).is_ok());
+    assert!(loaded.collapse_fully_backtracking(&SelectionStrategy::MinimizeEntropy).is_ok());
+    assert_eq!(solve.grid.to_str(), loaded.grid.to_str());
+    let reloaded = ConstraintSolver::load_setup(&loaded.save_setup().unwrap(), &test_registry()).unwrap();
+    assert_eq!(reloaded.grid.to_str(), loaded.grid.to_str());
+}
+
+#[test]
+fn test_tile_count_limits(){
+    let height =10;
+    let width = 10;
+    let mut data =Vec::new();
+    for i in 0..height{
+        for j in 0..width{
+            data.push(if (i*7+j*3)%10 == 0{1} else{0});
+        }
+    }
+    let mut solve = ConstraintSolver::new_from_data(&data, height, width).unwrap();
+    solve.add_global_constraint(TileCount::between(&[1], 3, 6)).unwrap();
+    solve.set_seed(5);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    let ones = solve.grid.values.iter().filter(|i| **i == 1).count();
+    assert!((3..=6).contains(&ones));
+    let loaded = ConstraintSolver::load_setup(&solve.save_setup().unwrap(), &ConstraintRegistry::new()).unwrap();
+    assert!(loaded.is_state_valid());
+    //no room for any 1s at all, so every cell gets forced to 0 before anything is picked
+    let mut solve = ConstraintSolver::new_from_data(&data, height, width).unwrap();
+    solve.add_global_constraint(TileCount::at_most(&[1], 0)).unwrap();
+    assert!(!solve.contains_undefined());
+    assert!(solve.grid.values.iter().all(|i| *i == 0));
+    //asking for a 1 now can't work, so it's turned down and the grid stays valid
+    assert!(solve.add_global_constraint(TileCount::at_least(&[1], 1)).is_err());
+    assert_eq!(solve.globals.len(), 1);
+    assert!(solve.is_state_valid());
+    let mut solve = ConstraintSolver::new_from_data(&data, height, width).unwrap();
+    assert!(solve.add_global_constraint(TileCount::at_least(&[1], 101)).is_err());
+    assert!(solve.globals.is_empty() && solve.undefined_count() == 100);
+}
+
+#[test]
+fn test_path_between_corners(){
+    //0 is a wall and 1 is floor, with mostly walls getting picked there's rarely a way through on its own
+    let mut borders = HashMap::new();
+    borders.insert(0, vec![HashSet::from([0, 1]); 8]);
+    borders.insert(1, vec![HashSet::from([0, 1]); 8]);
+    let mut solve = ConstraintSolver::new_from_borders(Arc::new([0, 1]), borders, 8, 8);
+    solve.add_global_constraint(PathExists::new((0, 0), (7, 7), &[1], false)).unwrap();
+    assert_eq!(*solve.grid.get_sq(0, 0), 1);
+    solve.set_seed(3);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::FromDistribution { distribution: Box::new([(0, 0.8), (1, 0.2)]) }).is_ok());
+    assert!(PathExists::new((0, 0), (7, 7), &[1], false).is_satisfied(&solve.grid));
+}
+
+#[test]
+fn test_pins_survive_backtracking(){
+    let mut borders = HashMap::new();
+    borders.insert(0, vec![HashSet::from([0, 1]); 8]);
+    borders.insert(1, vec![HashSet::from([0]); 8]);
+    let mut solve = ConstraintSolver::new_from_borders(Arc::new([0, 1]), borders, 5, 5);
+    //1s can't touch, so these two clash
+    assert!(matches!(solve.pin(&[(1, 1, 1), (2, 2, 1)]), Err(Error::Contradiction { .. })));
+    assert!(!solve.is_pinned(1, 1));
+    assert!(matches!(solve.pin(&[(5, 0, 1)]), Err(Error::OutOfBounds { x: 5, y: 0 })));
+    let mut partial = Grid::new(5, 5);
+    *partial.get_sqmut(1, 1) = 1;
+    *partial.get_sqmut(3, 3) = 1;
+    solve.pin_grid(&partial).unwrap();
+    assert_eq!(*solve.grid.get_sq(2, 2), 0);
+    //exactly one more 1 has to go somewhere around the pins
+    solve.add_global_constraint(TileCount::between(&[1], 3, 3)).unwrap();
+    solve.set_seed(2);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    assert_eq!(*solve.grid.get_sq(1, 1), 1);
+    assert_eq!(*solve.grid.get_sq(3, 3), 1);
+    while solve.backtrack(){}
+    solve.reset().unwrap();
+    assert_eq!(*solve.grid.get_sq(1, 1), 1);
+    assert!(solve.is_pinned(3, 3));
+}
+
+#[test]
+fn test_regenerate_region(){
+    let height = 12;
+    let width = 12;
+    let mut data = Vec::new();
+    for i in 0..height{
+        for j in 0..width{
+            data.push(((i+j)%3) as i32);
+        }
+    }
+    let mut solve = ConstraintSolver::new_from_data(&data, height, width).unwrap();
+    solve.set_seed(4);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    let before = solve.grid.clone();
+    let mut mask = vec![false; width*height];
+    for i in [13, 14, 25, 26, 100]{
+        mask[i] = true;
+    }
+    assert!(solve.regenerate_mask(&mask, &SelectionStrategy::PurelyRandom).is_ok());
+    assert!(solve.regenerate_rect(8, 8, 12, 12, &SelectionStrategy::MinimizeEntropy).is_ok());
+    assert!(solve.is_state_valid());
+    for y in 0..height{
+        for x in 0..width{
+            let regenerated = mask[y*width+x] || (x>= 8 && y>= 8);
+            if !regenerated{
+                assert_eq!(solve.grid.get_sq(x, y), before.get_sq(x, y));
+            }
+        }
+    }
+    assert!(matches!(solve.regenerate_mask(&[true], &SelectionStrategy::PurelyRandom), Err(Error::SizeMismatch { .. })));
+}
+
+#[test]
+fn test_wrapped_edges_match(){
+    let height = 6;
+    let width = 6;
+    let mut data = Vec::new();
+    for i in 0..height{
+        for j in 0..width{
+            data.push(((i+j)%3) as i32);
+        }
+    }
+    let mut solve = ConstraintSolver::new_from_data(&data, height, width).unwrap();
+    solve.set_boundary(Boundary::Wrap, Boundary::Open).unwrap();
+    solve.pin(&[(0, 0, 0)]).unwrap();
+    //the diagonal pattern carries on across the left edge
+    assert_eq!(*solve.grid.get_sq(5, 0), 2);
+    assert_eq!(*solve.grid.get_sq(5, 1), 0);
+    assert_eq!(solve.grid.neighbor(0, 5, -1, 1), None);
+    solve.set_seed(1);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    let loaded = ConstraintSolver::load_setup(&solve.save_setup().unwrap(), &ConstraintRegistry::new()).unwrap();
+    assert_eq!(loaded.grid.get_boundary(), (Boundary::Wrap, Boundary::Open));
+    assert!(loaded.is_state_valid());
+}
+
+#[test]
+fn test_edge_conditions(){
+    //0 is ocean, 1 is beach and 2 is land, ocean and land never touch
+    let mut borders = HashMap::new();
+    borders.insert(0, vec![HashSet::from([0, 1]); 8]);
+    borders.insert(1, vec![HashSet::from([0, 1, 2]); 8]);
+    borders.insert(2, vec![HashSet::from([1, 2]); 8]);
+    let mut solve = ConstraintSolver::new_from_borders(Arc::new([0, 1, 2]), borders, 6, 6);
+    solve.set_edges(Edges{right:EdgeCondition::Tile(2), ..Edges::all(EdgeCondition::Tile(0))}).unwrap();
+    assert_eq!(solve.allowed_states_at(0, 3), vec![0, 1]);
+    assert_eq!(solve.allowed_states_at(2, 2), vec![0, 1, 2]);
+    //ocean above and land to the right, only beach fits in the top right corner
+    assert_eq!(solve.allowed_states_at(5, 0), vec![1]);
+    solve.set_seed(3);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    assert!((0..6).all(|y| *solve.grid.get_sq(5, y) != 0));
+    //a coast that has to be beach all along the bottom
+    solve.set_edges(Edges{bottom:EdgeCondition::Allowed(vec![1]), ..Edges::default()}).unwrap();
+    assert_eq!(solve.allowed_states_at(2, 5), vec![1]);
+    assert!(!solve.check_collapse_allowed(3, 5, 2));
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    assert!((0..6).all(|x| *solve.grid.get_sq(x, 5) == 1));
+    solve.set_edges(Edges{right:EdgeCondition::Free, bottom:EdgeCondition::OneOf(vec![0, 1]), ..Edges::all(EdgeCondition::Tile(0))}).unwrap();
+    assert_eq!(solve.allowed_states_at(5, 3), vec![0, 1, 2]);
+    solve.set_seed(6);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    for i in 0..6{
+        assert_ne!(*solve.grid.get_sq(0, i), 2);
+        assert_ne!(*solve.grid.get_sq(i, 0), 2);
+        assert_ne!(*solve.grid.get_sq(i, 5), 2);
+    }
+}
+
+#[test]
+fn test_von_neumann_learning(){
+    //a checkerboard only says anything about the 4 sides, diagonals are left alone
+    let mut data = Vec::new();
+    for i in 0..4{
+        for j in 0..4{
+            data.push((i+j)%2);
+        }
+    }
+    let mut solve = ConstraintSolver::new_from_data_with_neighborhood(&data, 4, 4, Neighborhood::von_neumann()).unwrap();
+    let rule = solve.constraints[0].borders().unwrap();
+    assert_eq!(rule.0[&0].len(), 4);
+    solve.set_seed(8);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    for y in 0..4{
+        for x in 0..3{
+            assert_ne!(solve.grid.get_sq(x, y), solve.grid.get_sq(x+1, y));
+        }
+    }
+    let loaded = ConstraintSolver::load_setup(&solve.save_setup().unwrap(), &ConstraintRegistry::new()).unwrap();
+    assert_eq!(loaded.neighborhood, Neighborhood::von_neumann());
+    assert!(loaded.is_state_valid());
+    //saves from before neighborhoods were stored still load as the 8 around
+    let old = ConstraintRegistry::new().load_str(r#"{"borders":{"0":[[0],[0],[0],[0],[0],[0],[0],[0]]}}"#).unwrap();
+    assert_eq!(*old.borders().unwrap().1, Neighborhood::moore());
+    //8 sets of borders for a neighborhood of 4 is a mistake, not something to guess at
+    let eight = HashMap::from([(0, vec![HashSet::from([0]); 8])]);
+    assert!(matches!(GridConstraint::new_from_borders_with_neighborhood(eight, Neighborhood::von_neumann()), Err(Error::InvalidNeighborhood(_))));
+    assert!(ConstraintRegistry::new().load_str(r#"{"borders":{"0":[[0],[0],[0],[0]]}}"#).is_err());
+}
+
 #[allow(unused)]
 pub fn test_collapse(){
     let height:usize =100;
@@ -497,9 +2236,9 @@ pub fn test_collapse(){
         }
     }
     let mut solve=
-     ConstraintSolver::new_from_data(&data, height, width);
+     ConstraintSolver::new_from_data(&data, height, width).unwrap();
      solve.write_constraints_to_file("constraints.json");
-    assert!(solve.collapse_fully(&SelectionStrategy::PurelyRandom));
+    assert!(solve.collapse_fully(&SelectionStrategy::PurelyRandom).is_ok());
     eprintln!("{}", solve.grid.to_str());
 }
 
@@ -514,13 +2253,48 @@ pub fn test_city(){
         allowed_states.push(i);
     }
     let mut solve=
-     ConstraintSolver::new_from_borders(allowed_states.into(),allowed_border, height, width);
+     ConstraintSolver::new_from_borders(allowed_states.clone().into(),allowed_border.clone(), height, width);
     solve.write_constraints_to_file("constraints.json");
-    while !solve.collapse_fully(&SelectionStrategy::PurelyRandom){
-        solve.grid.reset();
+    //0 is empty ground, along each edge only tiles without a road leading off the map that way
+    let no_road_towards = |dir:usize| EdgeCondition::Allowed(allowed_states.iter().copied().filter(|t| *t == 0 || allowed_border[t][dir].iter().all(|o| *o == 0)).collect());
+    solve.set_edges(Edges{left:no_road_towards(3), right:no_road_towards(4), top:no_road_towards(1), bottom:no_road_towards(6)}).expect("empty ground fits along every edge");
+    //every road piece joins up with the rest
+    solve.add_global_constraint(Connected::new(&[1, 2, 3, 4], false)).expect("an empty grid can always be connected");
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    for i in 0..width{
+        assert!(solve.grid.edge_allows(i, 0, *solve.grid.get_sq(i, 0)) && solve.grid.edge_allows(i, height-1, *solve.grid.get_sq(i, height-1)));
+    }
+    for i in 0..height{
+        assert!(solve.grid.edge_allows(0, i, *solve.grid.get_sq(0, i)) && solve.grid.edge_allows(width-1, i, *solve.grid.get_sq(width-1, i)));
     }
     eprintln!("{}", solve.grid.to_str()); 
-    solve.grid.draw_as_byte_image(&tiles).export("city.png");
+    solve.grid.draw_as_byte_image(&tiles).expect("every tile has an image").export("city.png");
 
 }
 
+
+#[test]
+fn test_learn_non_square(){
+    //3 rows of 6: stripes going across, so every tile only ever has itself to its left and right
+    let data = [
+        0, 0, 0, 0, 0, 0,
+        1, 1, 1, 1, 1, 1,
+        0, 0, 0, 0, 0, 0,
+    ];
+    let (borders, allowed) = ConstraintSolver::constraints_and_allowed_from_data_with_neighborhood(&data, 3, 6, &Neighborhood::von_neumann()).unwrap();
+    assert_eq!(allowed, vec![0, 1]);
+    let (left, right) = (Neighborhood::von_neumann().index_of((-1, 0)).unwrap(), Neighborhood::von_neumann().index_of((1, 0)).unwrap());
+    assert_eq!(borders[&0][left], HashSet::from([0]));
+    assert_eq!(borders[&1][right], HashSet::from([1]));
+    let mut solve = ConstraintSolver::new_from_data_with_neighborhood(&data, 3, 6, Neighborhood::von_neumann()).unwrap().with_size(5, 8).unwrap();
+    assert_eq!(solve.get_statistics().unwrap().frequencies[&1], 6);
+    solve.set_seed(4);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::FromSample).is_ok());
+    for y in 0..5{
+        for x in 0..7{
+            assert_eq!(solve.grid.get_sq(x, y), solve.grid.get_sq(x+1, y));
+        }
+    }
+    let loaded = ConstraintSolver::load_setup(&solve.save_setup().unwrap(), &ConstraintRegistry::new()).unwrap();
+    assert!(loaded.get_statistics().is_some());
+}
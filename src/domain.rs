@@ -0,0 +1,139 @@
+//the set of values a cell can still take, stored as bits indexing into the solver's allowed_states
+//tilesets with up to 64 tiles fit in a single word, bigger ones spill onto the heap
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Domain{
+    Small(u64),
+    Large(Box<[u64]>),
+}
+
+impl Domain{
+    pub fn empty(size:usize)->Self{
+        if size<= 64{
+            Self::Small(0)
+        } else{
+            Self::Large(vec![0; size.div_ceil(64)].into_boxed_slice())
+        }
+    }
+
+    pub fn full(size:usize)->Self{
+        let mut out = Self::empty(size);
+        for i in 0..size{
+            out.insert(i);
+        }
+        out
+    }
+
+    pub fn single(size:usize, idx:usize)->Self{
+        let mut out = Self::empty(size);
+        out.insert(idx);
+        out
+    }
+
+    fn words(&self)->&[u64]{
+        match self{
+            Self::Small(w)=> std::slice::from_ref(w),
+            Self::Large(w)=> w,
+        }
+    }
+
+    fn words_mut(&mut self)->&mut [u64]{
+        match self{
+            Self::Small(w)=> std::slice::from_mut(w),
+            Self::Large(w)=> w,
+        }
+    }
+
+    pub fn contains(&self, idx:usize)->bool{
+        self.words().get(idx/64).is_some_and(|w| w & (1<<(idx%64)) != 0)
+    }
+
+    pub fn insert(&mut self, idx:usize){
+        self.words_mut()[idx/64] |= 1<<(idx%64);
+    }
+
+    //returns true if the value was in the domain
+    pub fn remove(&mut self, idx:usize)->bool{
+        let had = self.contains(idx);
+        if had{
+            self.words_mut()[idx/64] &= !(1<<(idx%64));
+        }
+        had
+    }
+
+    pub fn len(&self)->usize{
+        self.words().iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self)->bool{
+        self.words().iter().all(|w| *w == 0)
+    }
+
+    //returns true if anything was removed
+    pub fn intersect_with(&mut self, other:&Self)->bool{
+        let mut changed = false;
+        for (a, b) in self.words_mut().iter_mut().zip(other.words()){
+            let n = *a & *b;
+            changed |= n != *a;
+            *a = n;
+        }
+        changed
+    }
+
+    pub fn union_with(&mut self, other:&Self){
+        for (a, b) in self.words_mut().iter_mut().zip(other.words()){
+            *a |= *b;
+        }
+    }
+
+    pub fn intersects(&self, other:&Self)->bool{
+        self.words().iter().zip(other.words()).any(|(a, b)| a & b != 0)
+    }
+
+    pub fn is_subset_of(&self, other:&Self)->bool{
+        self.words().iter().zip(other.words()).all(|(a, b)| a & !b == 0)
+    }
+
+    pub fn first(&self)->Option<usize>{
+        self.iter().next()
+    }
+
+    pub fn iter(&self)->impl Iterator<Item = usize>+'_{
+        self.words().iter().enumerate().flat_map(|(i, w)|{
+            let mut bits = *w;
+            std::iter::from_fn(move ||{
+                if bits == 0{
+                    return None;
+                }
+                let b = bits.trailing_zeros() as usize;
+                bits &= bits-1;
+                Some(i*64+b)
+            })
+        })
+    }
+}
+
+#[test]
+fn test_domain_ops(){
+    for size in [10, 64, 130]{
+        let mut d = Domain::full(size);
+        assert_eq!(d.len(), size);
+        assert!(d.remove(3));
+        assert!(!d.remove(3));
+        assert!(!d.contains(3));
+        assert_eq!(d.len(), size-1);
+        let mut other = Domain::empty(size);
+        other.insert(3);
+        other.insert(5);
+        other.insert(size-1);
+        assert!(d.intersect_with(&other));
+        assert_eq!(d.iter().collect::<Vec<_>>(), vec![5, size-1]);
+        assert!(d.is_subset_of(&other));
+        assert!(!other.is_subset_of(&d));
+        assert!(d.intersects(&other));
+        assert!(!d.intersects(&Domain::single(size, 3)));
+        assert!(!d.intersect_with(&other));
+        d.union_with(&Domain::single(size, 0));
+        assert_eq!(d.first(), Some(0));
+        assert_eq!(d.len(), 3);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::tile_set::TileType;
+
+#[derive(Debug)]
+pub enum Error{
+    //a cell was left with no possible values
+    Contradiction{x:usize, y:usize},
+    //a global constraint like a tile count can't be satisfied any more
+    GlobalContradiction(String),
+    //every choice was tried and none of them worked
+    Unsatisfiable,
+    //a cell outside of the grid
+    OutOfBounds{x:usize, y:usize},
+    //a cell index past the end of a topology
+    CellOutOfBounds(usize),
+    //a grid or mask that doesn't have as many cells as the solver's grid
+    SizeMismatch{expected:usize, found:usize},
+    //a neighborhood with an offset that has no opposite, repeats or points at the cell itself
+    InvalidNeighborhood(String),
+    //a tile id that isn't one of the allowed states, or has no image in the tileset
+    TileOutOfRange(TileType),
+    Io(std::io::Error),
+    //an image could not be loaded
+    Decode(String),
+    //a sample image that can't be learned from, like one smaller than the patterns taken from it
+    InvalidSample(String),
+    //raylib failed to make a texture, render texture or shader
+    Graphics(String),
+    Serialization(serde_json::Error),
+    //a saved constraint with a tag nothing was registered under
+    UnknownConstraint(String),
+}
+
+impl std::fmt::Display for Error{
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>)->std::fmt::Result{
+        match self{
+            Self::Contradiction { x, y }=> write!(f, "contradiction: no values left for ({x}, {y})"),
+            Self::GlobalContradiction(c)=> write!(f, "contradiction: {c} can't be satisfied"),
+            Self::Unsatisfiable=> write!(f, "no assignment satisfies the constraints"),
+            Self::OutOfBounds { x, y }=> write!(f, "({x}, {y}) is outside of the grid"),
+            Self::CellOutOfBounds(c)=> write!(f, "cell {c} is outside of the grid"),
+            Self::SizeMismatch { expected, found }=> write!(f, "expected {expected} cells but got {found}"),
+            Self::InvalidNeighborhood(e)=> write!(f, "invalid neighborhood: {e}"),
+            Self::TileOutOfRange(t)=> write!(f, "tile {t} is out of range"),
+            Self::Io(e)=> write!(f, "io error: {e}"),
+            Self::Decode(e)=> write!(f, "failed to decode image: {e}"),
+            Self::InvalidSample(e)=> write!(f, "invalid sample: {e}"),
+            Self::Graphics(e)=> write!(f, "graphics error: {e}"),
+            Self::Serialization(e)=> write!(f, "serialization error: {e}"),
+            Self::UnknownConstraint(t)=> write!(f, "no constraint registered as {t}"),
+        }
+    }
+}
+
+impl std::error::Error for Error{
+    fn source(&self)->Option<&(dyn std::error::Error+'static)>{
+        match self{
+            Self::Io(e)=> Some(e),
+            Self::Serialization(e)=> Some(e),
+            _=> None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error{
+    fn from(value:std::io::Error)->Self{
+        Self::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for Error{
+    fn from(value:serde_json::Error)->Self{
+        Self::Serialization(value)
+    }
+}
@@ -0,0 +1,355 @@
+use std::sync::Arc;
+use serde_derive::{Deserialize, Serialize};
+use crate::{constraints::{Grid, Layout}, domain::Domain, error::Error, neighborhood::Neighborhood, tile_set::TileType};
+
+//a rule about the grid as a whole rather than one cell and its neighbors, like how often a tile shows up
+pub trait GlobalConstraint: std::fmt::Debug{
+    //the name the constraint is saved under, see ConstraintRegistry::register_global
+    fn tag(&self)->&'static str;
+
+    //new domains for the cells this constraint rules values out of, fails if it can't be satisfied any more.
+    //domains are indexed y*width+x and hold indices into allowed_states
+    fn narrow(&self, grid:&Grid, domains:&[Domain], allowed_states:&[TileType])->Result<Vec<(usize, Domain)>, Error>;
+
+    //true if a fully collapsed grid satisfies the constraint
+    fn is_satisfied(&self, grid:&Grid)->bool;
+
+    fn to_json(&self)->Result<serde_json::Value, Error>;
+}
+
+pub type GlobalConstraintLoader = fn(&serde_json::Value)->Result<Arc<dyn GlobalConstraint>, Error>;
+
+//the bits of allowed_states that are one of tiles
+pub fn tile_mask(tiles:&[TileType], allowed_states:&[TileType])->Domain{
+    let mut out = Domain::empty(allowed_states.len());
+    for (i, t) in allowed_states.iter().enumerate(){
+        if tiles.contains(t){
+            out.insert(i);
+        }
+    }
+    out
+}
+
+//every cell reachable from start through cells that are passable. on a square grid only across the 4 sides unless
+//diagonal is set, other layouts always go to every cell around
+fn flood(grid:&Grid, start:usize, diagonal:bool, passable:&[bool])->Vec<bool>{
+    let mut reached = vec![false; passable.len()];
+    if !passable[start]{
+        return reached;
+    }
+    let neighborhood = match grid.get_layout(){
+        Layout::Square if !diagonal=> Neighborhood::von_neumann(),
+        layout=> layout.neighborhood(),
+    };
+    let topology = grid.topology(&neighborhood);
+    reached[start] = true;
+    let mut stack = vec![start];
+    while let Some(i) = stack.pop(){
+        for (_, n) in topology.neighbors(i){
+            if passable[n] && !reached[n]{
+                reached[n] = true;
+                stack.push(n);
+            }
+        }
+    }
+    reached
+}
+
+//a limit on how many cells a tile may take up, either as a number of cells or a fraction of the grid
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CountLimit{
+    Count(usize),
+    Fraction(f64),
+}
+
+impl CountLimit{
+    //fractions round towards allowing fewer tiles for a maximum and more for a minimum
+    fn resolve(&self, cells:usize, round_up:bool)->usize{
+        match self{
+            Self::Count(c)=> *c,
+            Self::Fraction(f)=>{
+                let v = f.max(0.0)*cells as f64;
+                if round_up{v.ceil() as usize} else{v.floor() as usize}
+            }
+        }
+    }
+}
+
+//between min and max cells hold one of tiles
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TileCount{
+    pub tiles:Vec<TileType>,
+    pub min:CountLimit,
+    pub max:CountLimit,
+}
+
+impl TileCount{
+    pub fn between(tiles:&[TileType], min:usize, max:usize)->Self{
+        Self{tiles:tiles.to_vec(), min:CountLimit::Count(min), max:CountLimit::Count(max)}
+    }
+
+    //fractions of the whole grid, fraction_between(tiles, 0.0, 0.1) is at most 10%
+    pub fn fraction_between(tiles:&[TileType], min:f64, max:f64)->Self{
+        Self{tiles:tiles.to_vec(), min:CountLimit::Fraction(min), max:CountLimit::Fraction(max)}
+    }
+
+    pub fn at_most(tiles:&[TileType], max:usize)->Self{
+        Self::between(tiles, 0, max)
+    }
+
+    pub fn at_least(tiles:&[TileType], min:usize)->Self{
+        Self{tiles:tiles.to_vec(), min:CountLimit::Count(min), max:CountLimit::Fraction(1.0)}
+    }
+
+    pub fn load(value:&serde_json::Value)->Result<Arc<dyn GlobalConstraint>, Error>{
+        let out:Self = serde_json::from_value(value.clone())?;
+        Ok(Arc::new(out))
+    }
+}
+
+impl GlobalConstraint for TileCount{
+    fn tag(&self)->&'static str{
+        "tile_count"
+    }
+
+    //once max cells are certain to hold the tiles nothing else may take them, and once only min cells can still
+    //hold them all of those have to
+    fn narrow(&self, _grid:&Grid, domains:&[Domain], allowed_states:&[TileType])->Result<Vec<(usize, Domain)>, Error>{
+        let mask = tile_mask(&self.tiles, allowed_states);
+        let min = self.min.resolve(domains.len(), true);
+        let max = self.max.resolve(domains.len(), false);
+        let mut placed = 0;
+        let mut undecided = Vec::new();
+        for (i, d) in domains.iter().enumerate(){
+            if !d.intersects(&mask){
+                continue;
+            }
+            if d.is_subset_of(&mask){
+                placed += 1;
+            } else{
+                undecided.push(i);
+            }
+        }
+        if placed>max || placed+undecided.len()<min{
+            return Err(Error::GlobalContradiction(format!("{:?}", self)));
+        }
+        let mut out = Vec::new();
+        if placed == max{
+            for i in undecided{
+                let mut d = domains[i].clone();
+                for t in mask.iter(){
+                    d.remove(t);
+                }
+                out.push((i, d));
+            }
+        } else if placed+undecided.len() == min{
+            for i in undecided{
+                let mut d = domains[i].clone();
+                d.intersect_with(&mask);
+                out.push((i, d));
+            }
+        }
+        Ok(out)
+    }
+
+    fn is_satisfied(&self, grid:&Grid)->bool{
+        let cells = grid.get_width()*grid.get_height();
+        let mut count = 0;
+        for y in 0..grid.get_height(){
+            for x in 0..grid.get_width(){
+                if self.tiles.contains(grid.get_sq(x, y)){
+                    count += 1;
+                }
+            }
+        }
+        count>= self.min.resolve(cells, true) && count<= self.max.resolve(cells, false)
+    }
+
+    fn to_json(&self)->Result<serde_json::Value, Error>{
+        Ok(serde_json::to_value(self)?)
+    }
+}
+
+//every cell holding one of tiles can reach every other one through cells that hold them too, like a road network
+//without any pieces cut off. diagonal counts cells touching at a corner as connected
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Connected{
+    pub tiles:Vec<TileType>,
+    pub diagonal:bool,
+}
+
+impl Connected{
+    pub fn new(tiles:&[TileType], diagonal:bool)->Self{
+        Self{tiles:tiles.to_vec(), diagonal}
+    }
+
+    pub fn load(value:&serde_json::Value)->Result<Arc<dyn GlobalConstraint>, Error>{
+        let out:Self = serde_json::from_value(value.clone())?;
+        Ok(Arc::new(out))
+    }
+}
+
+impl GlobalConstraint for Connected{
+    fn tag(&self)->&'static str{
+        "connected"
+    }
+
+    //floods out from one cell that has to hold the tiles through every cell that still could. a placed cell
+    //the flood can't reach means the network is already split, and any other cell it can't reach
+    //could never be joined up so it loses the tiles
+    fn narrow(&self, grid:&Grid, domains:&[Domain], allowed_states:&[TileType])->Result<Vec<(usize, Domain)>, Error>{
+        let mask = tile_mask(&self.tiles, allowed_states);
+        let possible:Vec<bool> = domains.iter().map(|d| d.intersects(&mask)).collect();
+        let placed:Vec<bool> = domains.iter().map(|d| !d.is_empty() && d.is_subset_of(&mask)).collect();
+        let Some(start) = placed.iter().position(|p| *p) else{
+            return Ok(Vec::new());
+        };
+        let reached = flood(grid, start, self.diagonal, &possible);
+        if placed.iter().zip(&reached).any(|(p, r)| *p && !*r){
+            return Err(Error::GlobalContradiction(format!("{:?}", self)));
+        }
+        let mut out = Vec::new();
+        for i in 0..domains.len(){
+            if possible[i] && !reached[i]{
+                let mut d = domains[i].clone();
+                for t in mask.iter(){
+                    d.remove(t);
+                }
+                out.push((i, d));
+            }
+        }
+        Ok(out)
+    }
+
+    fn is_satisfied(&self, grid:&Grid)->bool{
+        let (width, height) = (grid.get_width(), grid.get_height());
+        let member:Vec<bool> = (0..width*height).map(|i| self.tiles.contains(grid.get_sq(i%width, i/width))).collect();
+        let Some(start) = member.iter().position(|m| *m) else{
+            return true;
+        };
+        let reached = flood(grid, start, self.diagonal, &member);
+        member == reached
+    }
+
+    fn to_json(&self)->Result<serde_json::Value, Error>{
+        Ok(serde_json::to_value(self)?)
+    }
+}
+
+//there's a way to walk from one cell to another stepping only on passable tiles, like the entrance and exit of a level
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PathExists{
+    pub from:(usize, usize),
+    pub to:(usize, usize),
+    pub passable:Vec<TileType>,
+    pub diagonal:bool,
+}
+
+impl PathExists{
+    pub fn new(from:(usize, usize), to:(usize, usize), passable:&[TileType], diagonal:bool)->Self{
+        Self{from, to, passable:passable.to_vec(), diagonal}
+    }
+
+    pub fn load(value:&serde_json::Value)->Result<Arc<dyn GlobalConstraint>, Error>{
+        let out:Self = serde_json::from_value(value.clone())?;
+        Ok(Arc::new(out))
+    }
+}
+
+impl GlobalConstraint for PathExists{
+    fn tag(&self)->&'static str{
+        "path_exists"
+    }
+
+    //both ends have to be passable, and fails as soon as every way between them goes through a cell that can't be
+    fn narrow(&self, grid:&Grid, domains:&[Domain], allowed_states:&[TileType])->Result<Vec<(usize, Domain)>, Error>{
+        let width = grid.get_width();
+        if self.from.0>= width || self.to.0>= width || self.from.1>= grid.get_height() || self.to.1>= grid.get_height(){
+            return Err(Error::GlobalContradiction(format!("{:?} is off the grid", self)));
+        }
+        let mask = tile_mask(&self.passable, allowed_states);
+        let possible:Vec<bool> = domains.iter().map(|d| d.intersects(&mask)).collect();
+        let from = self.from.1*width+self.from.0;
+        let to = self.to.1*width+self.to.0;
+        let reached = flood(grid, from, self.diagonal, &possible);
+        if !reached[to]{
+            return Err(Error::GlobalContradiction(format!("{:?}", self)));
+        }
+        let mut out = Vec::new();
+        for i in [from, to]{
+            let mut d = domains[i].clone();
+            if d.intersect_with(&mask){
+                out.push((i, d));
+            }
+        }
+        Ok(out)
+    }
+
+    fn is_satisfied(&self, grid:&Grid)->bool{
+        let (width, height) = (grid.get_width(), grid.get_height());
+        if self.from.0>= width || self.to.0>= width || self.from.1>= height || self.to.1>= height{
+            return false;
+        }
+        let passable:Vec<bool> = (0..width*height).map(|i| self.passable.contains(grid.get_sq(i%width, i/width))).collect();
+        flood(grid, self.from.1*width+self.from.0, self.diagonal, &passable)[self.to.1*width+self.to.0]
+    }
+
+    fn to_json(&self)->Result<serde_json::Value, Error>{
+        Ok(serde_json::to_value(self)?)
+    }
+}
+
+#[test]
+fn test_tile_count_narrow(){
+    let allowed = [0, 1];
+    let grid = Grid::new(2, 2);
+    let full = Domain::full(2);
+    let zero = Domain::single(2, 0);
+    let one = Domain::single(2, 1);
+    //two 1s already down, so the other cells lose 1
+    let count = TileCount::between(&[1], 0, 2);
+    let out = count.narrow(&grid, &[one.clone(), one.clone(), full.clone(), full.clone()], &allowed).unwrap();
+    assert_eq!(out, vec![(2, zero.clone()), (3, zero.clone())]);
+    //three 1s needed and only three cells left that can hold one
+    let count = TileCount::between(&[1], 3, 4);
+    let out = count.narrow(&grid, &[zero.clone(), one.clone(), full.clone(), full.clone()], &allowed).unwrap();
+    assert_eq!(out, vec![(2, one.clone()), (3, one.clone())]);
+    assert!(count.narrow(&grid, &[zero.clone(), zero.clone(), full.clone(), full.clone()], &allowed).is_err());
+    assert!(TileCount::fraction_between(&[0], 0.0, 0.25).narrow(&grid, &[zero.clone(), zero, full.clone(), full], &allowed).is_err());
+}
+
+#[test]
+fn test_connected_narrow(){
+    let allowed = [0, 1];
+    let full = Domain::full(2);
+    let zero = Domain::single(2, 0);
+    let one = Domain::single(2, 1);
+    //a road at the left end can't reach past the 0, so the right end can't be road
+    let row = Grid::new(1, 4);
+    let out = Connected::new(&[1], false).narrow(&row, &[one.clone(), full.clone(), zero.clone(), full.clone()], &allowed).unwrap();
+    assert_eq!(out, vec![(3, zero.clone())]);
+    assert!(Connected::new(&[1], false).narrow(&row, &[one.clone(), zero.clone(), full, one.clone()], &allowed).is_err());
+    //two roads touching at a corner are only joined when diagonals count
+    let square = Grid::new(2, 2);
+    let diag = [one.clone(), zero.clone(), zero, one];
+    assert!(Connected::new(&[1], false).narrow(&square, &diag, &allowed).is_err());
+    assert!(Connected::new(&[1], true).narrow(&square, &diag, &allowed).unwrap().is_empty());
+}
+
+#[test]
+fn test_path_exists_narrow(){
+    let allowed = [0, 1];
+    let full = Domain::full(2);
+    let zero = Domain::single(2, 0);
+    let one = Domain::single(2, 1);
+    let square = Grid::new(2, 2);
+    //the ends get forced to be passable while the rest stays open
+    let path = PathExists::new((0, 0), (1, 1), &[1], false);
+    let out = path.narrow(&square, &[full.clone(), full.clone(), full.clone(), full.clone()], &allowed).unwrap();
+    assert_eq!(out, vec![(0, one.clone()), (3, one.clone())]);
+    //walled off on both sides, only a diagonal step gets through
+    let walled = [one.clone(), zero.clone(), zero, one];
+    assert!(path.narrow(&square, &walled, &allowed).is_err());
+    assert!(PathExists::new((0, 0), (1, 1), &[1], true).narrow(&square, &walled, &allowed).unwrap().is_empty());
+    assert!(PathExists::new((0, 0), (2, 1), &[1], false).narrow(&square, &walled, &allowed).is_err());
+}
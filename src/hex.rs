@@ -0,0 +1,170 @@
+use serde_derive::{Deserialize, Serialize};
+use crate::{constraints::Grid, error::Error, images::ByteImage, neighborhood::Neighborhood, tile_set::TileSet, topology::Topology};
+
+//the six sides of a pointy topped hex. borders on a hex grid have one set per direction in this order,
+//and the opposite of direction i is (i+3)%6
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HexDirection{East, NorthEast, NorthWest, West, SouthWest, SouthEast}
+
+impl HexDirection{
+    pub const ALL:[HexDirection; 6] = [HexDirection::East, HexDirection::NorthEast, HexDirection::NorthWest, HexDirection::West, HexDirection::SouthWest, HexDirection::SouthEast];
+
+    pub fn index(&self)->usize{
+        *self as usize
+    }
+
+    pub fn opposite(&self)->Self{
+        Self::ALL[(self.index()+3)%6]
+    }
+
+    //the step in axial coordinates (q, r), where r is the row and q runs along it leaning left as rows go down
+    pub fn axial_offset(&self)->(isize, isize){
+        match self{
+            HexDirection::East=> (1, 0),
+            HexDirection::NorthEast=> (1, -1),
+            HexDirection::NorthWest=> (0, -1),
+            HexDirection::West=> (-1, 0),
+            HexDirection::SouthWest=> (-1, 1),
+            HexDirection::SouthEast=> (0, 1),
+        }
+    }
+}
+
+//the cells of a Grid laid out as Layout::Hex, rows of pointy topped hexes in "odd-r" offset coordinates where every odd row
+//is pushed half a hex to the right. directions are the offsets of neighborhood in axial coordinates, see Neighborhood::hex
+#[derive(Clone, Debug, PartialEq)]
+pub struct HexTopology{
+    height:usize,
+    width:usize,
+    neighborhood:Neighborhood,
+}
+
+impl HexTopology{
+    pub fn new(height:usize, width:usize, neighborhood:Neighborhood)->Self{
+        Self{height, width, neighborhood}
+    }
+
+    pub fn to_axial(x:usize, y:usize)->(isize, isize){
+        (x as isize-(y as isize-(y as isize&1))/2, y as isize)
+    }
+
+    pub fn from_axial(q:isize, r:isize)->(isize, isize){
+        (q+(r-(r&1))/2, r)
+    }
+
+    //the cell (dq, dr) away from (x, y) in axial coordinates, None if that's off the map
+    pub fn neighbor(&self, x:usize, y:usize, (dq, dr):(isize, isize))->Option<(usize, usize)>{
+        let (q, r) = Self::to_axial(x, y);
+        let (nx, ny) = Self::from_axial(q+dq, r+dr);
+        (nx>= 0 && ny>= 0 && (nx as usize)<self.width && (ny as usize)<self.height).then_some((nx as usize, ny as usize))
+    }
+}
+
+impl Topology for HexTopology{
+    fn cell_count(&self)->usize{
+        self.height*self.width
+    }
+
+    fn direction_count(&self)->usize{
+        self.neighborhood.len()
+    }
+
+    fn neighbors(&self, cell:usize)->Vec<(usize, usize)>{
+        let (x, y) = (cell%self.width, cell/self.width);
+        self.neighborhood.offsets().iter().enumerate().filter_map(|(dir, offset)| self.neighbor(x, y, *offset).map(|(nx, ny)| (dir, ny*self.width+nx))).collect()
+    }
+
+    fn inverse(&self, dir:usize)->usize{
+        self.neighborhood.opposite(dir)
+    }
+
+    fn direction_label(&self, dir:usize)->String{
+        let offset = self.neighborhood.offsets()[dir];
+        match HexDirection::ALL.iter().find(|d| d.axial_offset() == offset){
+            Some(d)=> format!("{d:?}"),
+            None=> format!("{offset:?}"),
+        }
+    }
+}
+
+//every cell of a hex grid drawn as its tile cut down to a hexagon as wide and tall as the tile, rows overlap by a quarter
+//of a tile so the hexes fit together
+pub(crate) fn draw_hexes(grid:&Grid, tileset:&TileSet)->Result<ByteImage, Error>{
+    let (height, width) = (grid.get_height(), grid.get_width());
+    let size = tileset.tile_size;
+    let row_step = size*3/4;
+    let out_width = width*size+if height>1{size/2} else{0};
+    let out_height = if height == 0{0} else{(height-1)*row_step+size};
+    let mut out = ByteImage::new_from_color(raylib::color::Color::BLACK, out_height, out_width);
+    for y in 0..height{
+        for x in 0..width{
+            let tile = tileset.get_tile(*grid.get_sq(x, y))?;
+            let left = x*size+if y%2 == 1{size/2} else{0};
+            let top = y*row_step;
+            for py in 0..size{
+                for px in 0..size{
+                    if hex_contains(px, py, size){
+                        out[top+py][left+px] = tile[py][px];
+                    }
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+//true if pixel (px, py) of a size x size square is inside the pointy topped hexagon that fills it
+fn hex_contains(px:usize, py:usize, size:usize)->bool{
+    //distances from the center, doubled so they stay whole numbers
+    let dx = (2*px as isize-(size as isize-1)).abs();
+    let dy = (2*py as isize-(size as isize-1)).abs();
+    2*dy+dx<= 2*size as isize
+}
+
+#[test]
+fn test_hex_solver(){
+    use crate::{constraints::{ConstraintRegistry, ConstraintSolver, Layout, SelectionStrategy}, images::Color, tile_set::TileType};
+    use std::collections::HashMap;
+    for x in 0..3{
+        for y in 0..3{
+            let (q, r) = HexTopology::to_axial(x, y);
+            assert_eq!(HexTopology::from_axial(q, r), (x as isize, y as isize));
+        }
+    }
+    let hexes = HexTopology::new(4, 4, Neighborhood::hex());
+    //odd rows sit further right, so going up and right from row 1 stays in the same column but not from row 2
+    assert_eq!(hexes.neighbor(1, 1, HexDirection::NorthEast.axial_offset()), Some((2, 0)));
+    assert_eq!(hexes.neighbor(1, 2, HexDirection::NorthEast.axial_offset()), Some((1, 1)));
+    assert_eq!(hexes.neighbor(0, 2, HexDirection::West.axial_offset()), None);
+    assert_eq!(hexes.direction_label(HexDirection::SouthWest.index()), "SouthWest");
+    //three tiles that never touch themselves, every hex has one of the other two on each side
+    let mut sample = Grid::new_with_layout(3, 6, Layout::Hex).unwrap();
+    for y in 0..3{
+        for x in 0..6{
+            let (q, r) = HexTopology::to_axial(x, y);
+            *sample.get_sqmut(x, y) = (q-r).rem_euclid(3) as TileType;
+        }
+    }
+    let mut solve = ConstraintSolver::new_from_sample(&sample, Neighborhood::hex()).unwrap().with_size(6, 6).unwrap();
+    solve.set_seed(3);
+    solve.pin(&[(2, 2, 1)]).unwrap();
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    let out = solve.get_grid();
+    assert_eq!(*out.get_layout(), Layout::Hex);
+    let loaded = ConstraintSolver::load_setup(&solve.save_setup().unwrap(), &ConstraintRegistry::new()).unwrap();
+    assert_eq!(loaded.get_grid().to_str(), out.to_str());
+    assert_eq!(*loaded.get_grid().get_layout(), Layout::Hex);
+    let topology = out.topology(&Neighborhood::hex());
+    for cell in 0..topology.cell_count(){
+        for (_, other) in topology.neighbors(cell){
+            assert_ne!(out.get_sq(cell%6, cell/6), out.get_sq(other%6, other/6));
+        }
+    }
+    let colors = [Color::RED, Color::GREEN, Color::BLUE];
+    let tileset = TileSet{tile_size:8, tiles:HashMap::from_iter((0..3).map(|i| (i, ByteImage::new_from_color(colors[i as usize], 8, 8))))};
+    let image = out.draw_as_byte_image(&tileset).unwrap();
+    assert_eq!((image.get_width(), image.get_height()), (52, 38));
+    //the corner of the first hex is left empty, its middle isn't
+    assert_eq!(image[0][0], Color::BLACK);
+    assert_eq!(image[4][4], colors[*out.get_sq(0, 0) as usize]);
+}
@@ -5,6 +5,7 @@ pub use raylib::prelude::Image;
 use raylib::shaders::RaylibShader;
 use raylib::texture::RaylibTexture2D;
 use serde_derive::Serialize;
+use crate::error::Error;
 use std::ops::Index;
 use std::ops::IndexMut;
 use std::sync::Arc;
@@ -117,8 +118,8 @@ impl ByteImage{
         Self{colors:vcolors.into_boxed_slice(), height, width}
     }
     
-    pub fn new_from_file(path:&str)->Result<Self,String>{
-        let mut img = Image::load_image(path)?;
+    pub fn new_from_file(path:&str)->Result<Self,Error>{
+        let mut img = Image::load_image(path).map_err(Error::Decode)?;
         Ok(Self::new(&mut img))
     }
 
@@ -152,7 +153,7 @@ impl ByteImage{
         let mut out_buff = Vec::new();
         out_buff.reserve_exact((x_end-x_start)*(y_end-y_start));
         for y in y_start..y_end{
-            for x in x_end..x_end{
+            for x in x_start..x_end{
                 out_buff.push(InternalColor::from(&self[y][x]));
             }
         }
@@ -254,12 +255,12 @@ impl ByteImage{
        
     }
 
-    pub fn blur_shader(&self, thread:&raylib::prelude::RaylibThread, handle:&mut raylib::prelude::RaylibHandle, kernel_size:usize, divisor:f64)->Result<Self, String>{
+    pub fn blur_shader(&self, thread:&raylib::prelude::RaylibThread, handle:&mut raylib::prelude::RaylibHandle, kernel_size:usize, divisor:f64)->Result<Self, Error>{
         static VS_CODE:&str = core::include_str!("shaders/blur.vs");
         static FS_CODE:&str = core::include_str!("shaders/blur.fs");
         let img = self.to_image();
-        let tex = handle.load_texture_from_image(thread, &img)?;
-        let render_tex = handle.load_render_texture(thread, self.width as u32, self.height as u32)?;
+        let tex = handle.load_texture_from_image(thread, &img).map_err(Error::Graphics)?;
+        let render_tex = handle.load_render_texture(thread, self.width as u32, self.height as u32).map_err(Error::Graphics)?;
         
         let mut shader = handle.load_shader_from_memory(thread, Some(VS_CODE), Some(FS_CODE)); 
         shader.set_shader_value_v(shader.get_shader_location("height"), &[self.height as i32]);
@@ -287,7 +288,7 @@ impl ByteImage{
                 EndTextureMode();
             }
         }
-        let mut img = render_tex.load_image()?;
+        let mut img = render_tex.load_image().map_err(Error::Graphics)?;
         Ok(Self::new(&mut img))
     }
 
@@ -318,12 +319,12 @@ impl ByteImage{
     }
 
 
-    pub fn guass_diff_shader(&self, thread:&raylib::prelude::RaylibThread, handle:&mut raylib::prelude::RaylibHandle, kernel_size0:usize, divisor0:f64, kernel_size1:usize, divisor1:f64, b_and_w:bool)->Result<Self, String>{
+    pub fn guass_diff_shader(&self, thread:&raylib::prelude::RaylibThread, handle:&mut raylib::prelude::RaylibHandle, kernel_size0:usize, divisor0:f64, kernel_size1:usize, divisor1:f64, b_and_w:bool)->Result<Self, Error>{
         static VS_CODE:&str = core::include_str!("shaders/blur.vs");
         static FS_CODE:&str = core::include_str!("shaders/diff.fs");
         let img = self.to_image();
-        let tex = handle.load_texture_from_image(thread, &img)?;
-        let render_tex = handle.load_render_texture(thread, self.width as u32, self.height as u32)?;
+        let tex = handle.load_texture_from_image(thread, &img).map_err(Error::Graphics)?;
+        let render_tex = handle.load_render_texture(thread, self.width as u32, self.height as u32).map_err(Error::Graphics)?;
         
         let mut shader = handle.load_shader_from_memory(thread, Some(VS_CODE), Some(FS_CODE)); 
         shader.set_shader_value_v(shader.get_shader_location("height"), &[self.height as i32]);
@@ -354,15 +355,15 @@ impl ByteImage{
                 EndTextureMode();
             }
         }
-        let mut img = render_tex.load_image()?;
+        let mut img = render_tex.load_image().map_err(Error::Graphics)?;
         Ok(Self::new(&mut img))
     }
-    pub fn cell_shader(&self, thread:&raylib::prelude::RaylibThread, handle:&mut raylib::prelude::RaylibHandle, kernel_size:usize, divisor:f64)->Result<Self, String>{
+    pub fn cell_shader(&self, thread:&raylib::prelude::RaylibThread, handle:&mut raylib::prelude::RaylibHandle, kernel_size:usize, divisor:f64)->Result<Self, Error>{
         static VS_CODE:&str = core::include_str!("shaders/blur.vs");
         static FS_CODE:&str = core::include_str!("shaders/cell.fs");
         let img = self.to_image();
-        let tex = handle.load_texture_from_image(thread, &img)?;
-        let render_tex = handle.load_render_texture(thread, self.width as u32, self.height as u32)?;
+        let tex = handle.load_texture_from_image(thread, &img).map_err(Error::Graphics)?;
+        let render_tex = handle.load_render_texture(thread, self.width as u32, self.height as u32).map_err(Error::Graphics)?;
         
         let mut shader = handle.load_shader_from_memory(thread, Some(VS_CODE), Some(FS_CODE)); 
         shader.set_shader_value_v(shader.get_shader_location("height"), &[self.height as i32]);
@@ -390,7 +391,7 @@ impl ByteImage{
                 EndTextureMode();
             }
         }
-        let mut img = render_tex.load_image()?;
+        let mut img = render_tex.load_image().map_err(Error::Graphics)?;
         Ok(Self::new(&mut img))
     }
 
@@ -1,7 +1,18 @@
+pub mod chunks;
 pub mod constraints;
+pub mod domain;
+pub mod error;
+pub mod global;
+pub mod hex;
+pub mod neighborhood;
+pub mod overlapping;
+pub mod statistics;
+pub mod symmetry;
 mod utils;
 pub mod images;
 pub mod tile_set;
+pub mod topology;
+pub mod voxel;
 #[allow(unused)]
 use std::{thread::sleep, time::{Duration, SystemTime}};
 use constraints::test_city;
@@ -0,0 +1,150 @@
+use serde_derive::{Deserialize, Serialize};
+use crate::{error::Error, hex::HexDirection, utils};
+
+//the cells a border constraint looks at, as offsets from the cell. opposites[i] is the index of the offset that points
+//back the other way, so if b is in direction i of a then a is in direction opposites[i] of b.
+//layouts whose directions aren't offsets, like voxels and graphs, use one with only the opposites, see directions
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawNeighborhood")]
+pub struct Neighborhood{
+    offsets:Vec<(isize, isize)>,
+    opposites:Vec<usize>,
+}
+
+impl Neighborhood{
+    //all 8 cells around, in the same order as utils::OFFSETS
+    pub fn moore()->Self{
+        Self{offsets:utils::OFFSETS.to_vec(), opposites:utils::MINUS_INDICES.to_vec()}
+    }
+
+    //just the 4 cells sharing a side: up, left, right, down
+    pub fn von_neumann()->Self{
+        Self{offsets:vec![(0, -1), (-1, 0), (1, 0), (0, 1)], opposites:vec![3, 2, 1, 0]}
+    }
+
+    //the 6 sides of a hex as axial offsets, in the order of HexDirection::ALL. see Layout::Hex
+    pub fn hex()->Self{
+        Self{offsets:HexDirection::ALL.iter().map(|d| d.axial_offset()).collect(), opposites:HexDirection::ALL.iter().map(|d| d.opposite().index()).collect()}
+    }
+
+    //any stencil, fails unless every offset has its opposite in the list and none of them repeat or point at the cell itself
+    pub fn custom(offsets:Vec<(isize, isize)>, opposites:Vec<usize>)->Result<Self, Error>{
+        if offsets.len() != opposites.len(){
+            return Err(Error::InvalidNeighborhood("every offset needs an opposite".to_string()));
+        }
+        for (i, (dx, dy)) in offsets.iter().enumerate(){
+            if (*dx, *dy) == (0, 0) || offsets[..i].contains(&(*dx, *dy)){
+                return Err(Error::InvalidNeighborhood(format!("({dx}, {dy}) is the cell itself or a repeat")));
+            }
+            if offsets.get(opposites[i]) != Some(&(-dx, -dy)){
+                return Err(Error::InvalidNeighborhood(format!("the opposite of ({dx}, {dy}) isn't ({}, {})", -dx, -dy)));
+            }
+        }
+        Ok(Self{offsets, opposites})
+    }
+
+    //directions without offsets, where the layout's topology says which cell each one goes to.
+    //fails unless every direction and its opposite point back at each other
+    pub fn directions(opposites:Vec<usize>)->Result<Self, Error>{
+        for (i, o) in opposites.iter().enumerate(){
+            if opposites.get(*o) != Some(&i){
+                return Err(Error::InvalidNeighborhood(format!("direction {i} has {o} as its opposite but not the other way around")));
+            }
+        }
+        Ok(Self{offsets:Vec::new(), opposites})
+    }
+
+    //every offset of any of them, None if there aren't any. directions without offsets can't be told apart,
+    //so the first neighborhood like that is used as it is
+    pub fn union<'a>(neighborhoods:impl IntoIterator<Item = &'a Self>)->Option<Self>{
+        let mut offsets:Vec<(isize, isize)> = Vec::new();
+        let mut any = false;
+        for n in neighborhoods{
+            if !n.has_offsets(){
+                return Some(n.clone());
+            }
+            any = true;
+            for o in &n.offsets{
+                if !offsets.contains(o){
+                    offsets.push(*o);
+                }
+            }
+        }
+        if !any{
+            return None;
+        }
+        let opposites = offsets.iter().map(|(dx, dy)| offsets.iter().position(|o| *o == (-dx, -dy)).expect("neighborhoods are symmetric")).collect();
+        Some(Self{offsets, opposites})
+    }
+
+    pub fn len(&self)->usize{
+        self.opposites.len()
+    }
+
+    pub fn is_empty(&self)->bool{
+        self.opposites.is_empty()
+    }
+
+    //false for one made with directions
+    pub fn has_offsets(&self)->bool{
+        self.offsets.len() == self.opposites.len()
+    }
+
+    pub fn offsets(&self)->&[(isize, isize)]{
+        &self.offsets
+    }
+
+    pub fn opposite(&self, dir:usize)->usize{
+        self.opposites[dir]
+    }
+
+    pub fn index_of(&self, offset:(isize, isize))->Option<usize>{
+        self.offsets.iter().position(|o| *o == offset)
+    }
+}
+
+//what gets loaded before it's checked by Neighborhood::custom
+#[derive(Deserialize)]
+struct RawNeighborhood{
+    offsets:Vec<(isize, isize)>,
+    opposites:Vec<usize>,
+}
+
+impl TryFrom<RawNeighborhood> for Neighborhood{
+    type Error = Error;
+
+    fn try_from(value:RawNeighborhood)->Result<Self, Error>{
+        if value.offsets.is_empty(){
+            return Self::directions(value.opposites);
+        }
+        Self::custom(value.offsets, value.opposites)
+    }
+}
+
+impl Default for Neighborhood{
+    fn default()->Self{
+        Self::moore()
+    }
+}
+
+#[test]
+fn test_neighborhoods(){
+    for n in [Neighborhood::moore(), Neighborhood::von_neumann()]{
+        for (i, (dx, dy)) in n.offsets().iter().enumerate(){
+            assert_eq!(n.offsets()[n.opposite(i)], (-dx, -dy));
+        }
+    }
+    let knight = Neighborhood::custom(vec![(1, 2), (-1, -2)], vec![1, 0]).unwrap();
+    assert!(Neighborhood::custom(vec![(1, 2), (-1, -2)], vec![0, 1]).is_err());
+    assert!(Neighborhood::custom(vec![(1, 2)], vec![0]).is_err());
+    let both = Neighborhood::union([&Neighborhood::von_neumann(), &knight, &Neighborhood::von_neumann()]).unwrap();
+    assert_eq!(both.len(), 6);
+    assert_eq!(both.opposite(both.index_of((1, 2)).unwrap()), both.index_of((-1, -2)).unwrap());
+    assert_eq!(serde_json::from_value::<Neighborhood>(serde_json::to_value(&knight).unwrap()).unwrap(), knight);
+    assert!(serde_json::from_str::<Neighborhood>(r#"{"offsets":[[1,2],[-1,-2]],"opposites":[0,1]}"#).is_err());
+    let up_down = Neighborhood::directions(vec![1, 0, 2]).unwrap();
+    assert!(!up_down.has_offsets() && up_down.len() == 3);
+    assert!(Neighborhood::directions(vec![1, 2, 0]).is_err());
+    assert_eq!(serde_json::from_value::<Neighborhood>(serde_json::to_value(&up_down).unwrap()).unwrap(), up_down);
+    assert_eq!(Neighborhood::union([&up_down, &knight]), Some(up_down));
+}
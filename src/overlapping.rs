@@ -0,0 +1,152 @@
+use std::collections::{HashMap, HashSet};
+use crate::{constraints::{Borders, Boundary, ConstraintSolver, Grid, GridConstraint}, error::Error, images::{ByteImage, Color}, neighborhood::Neighborhood, tile_set::TileType};
+
+//the overlapping model: every n x n block of pixels in a sample is a pattern, and two patterns may sit next to each other
+//wherever they agree on the pixels they share. the solver places patterns and every cell shows the top left pixel of its pattern
+#[derive(Clone, Debug)]
+pub struct OverlappingModel{
+    n:usize,
+    //n*n colors each, row by row. a pattern's tile id is its index
+    patterns:Vec<Vec<Color>>,
+    //how often each pattern shows up in the sample
+    counts:Vec<usize>,
+}
+
+fn color_key(c:&Color)->u32{
+    u32::from_le_bytes([c.r, c.g, c.b, c.a])
+}
+
+impl OverlappingModel{
+    //reads every n x n block of sample, with wrap blocks also run over the right and bottom edges back to the start
+    pub fn new(sample:&ByteImage, n:usize, wrap:bool)->Result<Self, Error>{
+        let (width, height) = (sample.get_width(), sample.get_height());
+        if n == 0 || n>width || n>height{
+            return Err(Error::InvalidSample(format!("{width}x{height} is too small for {n}x{n} patterns")));
+        }
+        let (x_count, y_count) = if wrap{(width, height)} else{(width-n+1, height-n+1)};
+        let mut index:HashMap<Vec<u32>, usize> = HashMap::new();
+        let mut patterns = Vec::new();
+        let mut counts = Vec::new();
+        for y in 0..y_count{
+            for x in 0..x_count{
+                let mut pattern = Vec::with_capacity(n*n);
+                for dy in 0..n{
+                    for dx in 0..n{
+                        pattern.push(sample[(y+dy)%height][(x+dx)%width]);
+                    }
+                }
+                let key:Vec<u32> = pattern.iter().map(color_key).collect();
+                let idx = *index.entry(key).or_insert_with(||{
+                    patterns.push(pattern);
+                    counts.push(0);
+                    patterns.len()-1
+                });
+                counts[idx] += 1;
+            }
+        }
+        Ok(Self{n, patterns, counts})
+    }
+
+    pub fn pattern_count(&self)->usize{
+        self.patterns.len()
+    }
+
+    pub fn pattern_size(&self)->usize{
+        self.n
+    }
+
+    //how often every pattern showed up, for set_weights or SelectionStrategy::FromDistribution
+    pub fn frequencies(&self)->Vec<(TileType, f64)>{
+        self.counts.iter().enumerate().map(|(i, c)| (i as TileType, *c as f64)).collect()
+    }
+
+    //true if b can sit dx, dy away from a, every pixel the two cover has to match
+    fn agrees(&self, a:usize, b:usize, dx:isize, dy:isize)->bool{
+        let n = self.n as isize;
+        for y in dy.max(0)..(n+dy).min(n){
+            for x in dx.max(0)..(n+dx).min(n){
+                let pa = self.patterns[a][(y*n+x) as usize];
+                let pb = self.patterns[b][((y-dy)*n+x-dx) as usize];
+                if pa != pb{
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    //which patterns may sit in each direction of neighborhood from each pattern
+    pub fn borders(&self, neighborhood:&Neighborhood)->Borders{
+        let mut out = HashMap::new();
+        for a in 0..self.patterns.len(){
+            let mut dirs = Vec::with_capacity(neighborhood.len());
+            for (dx, dy) in neighborhood.offsets(){
+                let allowed:HashSet<TileType> = (0..self.patterns.len()).filter(|b| self.agrees(a, *b, *dx, *dy)).map(|b| b as TileType).collect();
+                dirs.push(allowed);
+            }
+            out.insert(a as TileType, dirs);
+        }
+        out
+    }
+
+    //a solver for a height x width grid of patterns, weighted by how often they showed up
+    pub fn solver(&self, height:usize, width:usize)->ConstraintSolver{
+        let allowed_states:Vec<TileType> = (0..self.patterns.len() as TileType).collect();
+        let neighborhood = Neighborhood::moore();
+        let constraints = vec![GridConstraint::new_from_borders_with_neighborhood(self.borders(&neighborhood), neighborhood).expect("every pattern has a set for every direction")];
+        let mut out = ConstraintSolver::new_with_constraints(height, width, allowed_states.into(), constraints);
+        out.set_weights(&self.frequencies());
+        out
+    }
+
+    //turns a solved grid back into pixels. along an axis that wraps the image is as big as the grid, otherwise
+    //the last cell's pattern is drawn out in full so the image is n-1 pixels bigger. fails on a grid with no cells
+    pub fn render(&self, grid:&Grid)->Result<ByteImage, Error>{
+        let (width, height) = (grid.get_width(), grid.get_height());
+        if width == 0 || height == 0{
+            return Err(Error::SizeMismatch { expected: 1, found: 0 });
+        }
+        let (horizontal, vertical) = grid.get_boundary();
+        let out_width = if horizontal == Boundary::Wrap{width} else{width+self.n-1};
+        let out_height = if vertical == Boundary::Wrap{height} else{height+self.n-1};
+        let mut colors = Vec::with_capacity(out_width*out_height);
+        for py in 0..out_height{
+            for px in 0..out_width{
+                let (cx, cy) = (px.min(width-1), py.min(height-1));
+                let tile = *grid.get_sq(cx, cy);
+                let pattern = self.patterns.get(tile as usize).filter(|_| tile>= 0).ok_or(Error::TileOutOfRange(tile))?;
+                colors.push(pattern[(py-cy)*self.n+px-cx]);
+            }
+        }
+        Ok(ByteImage::new_from_colors(&colors, out_height, out_width))
+    }
+}
+
+#[test]
+fn test_overlapping_model(){
+    use crate::constraints::SelectionStrategy;
+    //a blue cross on white, over and over
+    let (w, b) = (Color::WHITE, Color::BLUE);
+    let sample = ByteImage::new_from_colors(&[
+        w, b, w, w,
+        b, b, b, w,
+        w, b, w, w,
+        w, w, w, w,
+    ], 4, 4);
+    let model = OverlappingModel::new(&sample, 2, true).unwrap();
+    //16 blocks when wrapping, some of them the same
+    assert!(model.pattern_count()<16);
+    assert_eq!(model.frequencies().iter().map(|f| f.1).sum::<f64>(), 16.0);
+    assert!(OverlappingModel::new(&sample, 5, false).is_err());
+    let mut solve = model.solver(8, 8);
+    solve.set_seed(2);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::FromDistribution { distribution: model.frequencies().into() }).is_ok());
+    let out = model.render(solve.get_grid()).unwrap();
+    assert!(matches!(model.render(&Grid::new(0, 4)), Err(Error::SizeMismatch { expected:1, found:0 })));
+    assert_eq!((out.get_width(), out.get_height()), (9, 9));
+    //every 2x2 block of the output has to be somewhere in the sample
+    let again = OverlappingModel::new(&out, 2, false).unwrap();
+    for p in &again.patterns{
+        assert!(model.patterns.contains(p));
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::{HashMap, HashSet};
+use serde_derive::{Deserialize, Serialize};
+use crate::{constraints::{Borders, Grid}, error::Error, neighborhood::Neighborhood, symmetry::{Symmetry, TileVariants}, tile_set::TileType};
+
+//what was learned from an example grid, how often every tile shows up and how often it sits next to every other tile
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SampleStatistics{
+    pub neighborhood:Neighborhood,
+    //every tile in the sample, in the order they first show up
+    pub tiles:Vec<TileType>,
+    pub frequencies:HashMap<TileType, usize>,
+    //neighbor_counts[tile][dir][other] is how often other sat in direction dir of tile
+    pub neighbor_counts:HashMap<TileType, Vec<HashMap<TileType, usize>>>,
+}
+
+impl SampleStatistics{
+    //data is height rows of width tiles
+    pub fn from_data(data:&[TileType], height:usize, width:usize, neighborhood:&Neighborhood)->Result<Self, Error>{
+        Ok(Self::from_grid(&Grid::new_from_data(data, height, width)?, neighborhood))
+    }
+
+    //learns from a grid laid out any way, counting neighbors in the directions its topology gives neighborhood
+    pub fn from_grid(sample:&Grid, neighborhood:&Neighborhood)->Self{
+        let topology = sample.topology(neighborhood);
+        let mut tiles = Vec::new();
+        let mut frequencies = HashMap::new();
+        let mut neighbor_counts:HashMap<TileType, Vec<HashMap<TileType, usize>>> = HashMap::new();
+        for cell in 0..topology.cell_count(){
+            let (x, y) = sample.position(cell);
+            let tile = *sample.get_sq(x, y);
+            *frequencies.entry(tile).or_insert_with(||{
+                tiles.push(tile);
+                0
+            }) += 1;
+            let counts = neighbor_counts.entry(tile).or_insert_with(|| vec![HashMap::new(); topology.direction_count()]);
+            for (dir, other) in topology.neighbors(cell){
+                let (nx, ny) = sample.position(other);
+                *counts[dir].entry(*sample.get_sq(nx, ny)).or_insert(0) += 1;
+            }
+        }
+        Self{neighborhood:neighborhood.clone(), tiles, frequencies, neighbor_counts}
+    }
+
+    //adds what the sample would have shown turned or mirrored by each of symmetries, tiles become their variants and
+    //directions turn with them. fails if the neighborhood doesn't turn into itself, like a stencil that only looks right,
+    //or has no offsets to turn, like the directions of a voxel grid or a graph
+    pub fn augmented(&self, symmetries:&[Symmetry], variants:&TileVariants)->Result<Self, Error>{
+        if !self.neighborhood.has_offsets(){
+            return Err(Error::InvalidNeighborhood("directions without offsets can't be turned or mirrored".to_string()));
+        }
+        let mut out = self.clone();
+        for s in symmetries{
+            if *s == Symmetry::Identity{
+                continue;
+            }
+            let dirs = self.neighborhood.offsets().iter().map(|o|{
+                self.neighborhood.index_of(s.apply(*o)).ok_or(Error::InvalidNeighborhood(format!("{o:?} turns into {:?} under {s:?}, which isn't in it", s.apply(*o))))
+            }).collect::<Result<Vec<usize>, Error>>()?;
+            if dirs.len() != self.neighborhood.len(){
+                return Err(Error::InvalidNeighborhood(format!("{} offsets for {} directions", dirs.len(), self.neighborhood.len())));
+            }
+            for tile in &self.tiles{
+                let variant = variants.get(*tile, *s);
+                if !out.frequencies.contains_key(&variant){
+                    out.tiles.push(variant);
+                }
+                *out.frequencies.entry(variant).or_insert(0) += self.frequencies[tile];
+                let counts = out.neighbor_counts.entry(variant).or_insert_with(|| vec![HashMap::new(); self.neighborhood.len()]);
+                for (dir, others) in self.neighbor_counts[tile].iter().enumerate(){
+                    for (other, count) in others{
+                        *counts[dirs[dir]].entry(variants.get(*other, *s)).or_insert(0) += count;
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    //every pair of tiles that sat next to each other at least once
+    pub fn borders(&self)->Borders{
+        self.neighbor_counts.iter().map(|(t, dirs)|{
+            (*t, dirs.iter().map(|d| d.keys().copied().collect::<HashSet<TileType>>()).collect())
+        }).collect()
+    }
+
+    //frequencies as weights for ConstraintSolver::set_weights
+    pub fn weights(&self)->Vec<(TileType, f64)>{
+        self.tiles.iter().map(|t| (*t, self.frequencies[t] as f64)).collect()
+    }
+
+    //how likely tile is at (x, y) going by the neighbors already in the grid, treating every direction as independent:
+    //frequency of the tile times, for every placed neighbor, the share of that tile's neighbors in that direction that looked like it
+    pub fn likelihood(&self, grid:&Grid, tile:TileType, x:usize, y:usize)->f64{
+        let Some(freq) = self.frequencies.get(&tile).map(|f| *f as f64) else{
+            return 0.0;
+        };
+        let counts = &self.neighbor_counts[&tile];
+        let mut out = freq;
+        for (dir, other) in grid.topology(&self.neighborhood).neighbors(grid.index(x, y)){
+            let (nx, ny) = grid.position(other);
+            let n = *grid.get_sq(nx, ny);
+            if n == -1{
+                continue;
+            }
+            out *= counts[dir].get(&n).copied().unwrap_or(0) as f64/freq;
+        }
+        out
+    }
+}
+
+#[test]
+fn test_sample_statistics(){
+    //3 rows of 5, wider than it's tall
+    let data = [
+        0, 0, 1, 2, 2,
+        0, 0, 1, 2, 2,
+        0, 0, 1, 2, 2,
+    ];
+    let stats = SampleStatistics::from_data(&data, 3, 5, &Neighborhood::von_neumann()).unwrap();
+    assert!(matches!(SampleStatistics::from_data(&data, 5, 5, &Neighborhood::von_neumann()), Err(Error::SizeMismatch { expected:25, found:15 })));
+    assert_eq!(stats.tiles, vec![0, 1, 2]);
+    assert_eq!(stats.frequencies[&2], 6);
+    //right of a 1 is always a 2, and 0 never touches 2
+    let right = Neighborhood::von_neumann().index_of((1, 0)).unwrap();
+    assert_eq!(stats.neighbor_counts[&1][right], HashMap::from([(2, 3)]));
+    assert_eq!(stats.borders()[&0][right], HashSet::from([0, 1]));
+    let mut grid = Grid::new(3, 5);
+    *grid.get_sqmut(1, 1) = 1;
+    //a 0 never has a 1 to its left, a 2 always does
+    assert_eq!(stats.likelihood(&grid, 0, 2, 1), 0.0);
+    assert!(stats.likelihood(&grid, 2, 2, 1)>0.0);
+}
+
+#[test]
+fn test_augmented_statistics(){
+    //0 is empty, 1 a road going across and 2 the same road going down. the sample only has the road going across
+    let data = [
+        0, 0, 0,
+        1, 1, 1,
+        0, 0, 0,
+    ];
+    let stats = SampleStatistics::from_data(&data, 3, 3, &Neighborhood::moore()).unwrap();
+    let mut variants = TileVariants::new();
+    variants.set_rotations([1, 2, 1, 2]);
+    let turned = stats.augmented(&Symmetry::ROTATIONS, &variants).unwrap();
+    assert_eq!(turned.tiles, vec![0, 1, 2]);
+    assert_eq!(turned.frequencies[&0], 24);
+    let (up, right) = (Neighborhood::moore().index_of((0, -1)).unwrap(), Neighborhood::moore().index_of((1, 0)).unwrap());
+    assert_eq!(turned.borders()[&2][up], HashSet::from([2]));
+    assert_eq!(turned.borders()[&2][right], HashSet::from([0]));
+    assert_eq!(turned.borders()[&1][right], HashSet::from([1]));
+    let lopsided = Neighborhood::custom(vec![(1, 0), (-1, 0)], vec![1, 0]).unwrap();
+    assert!(SampleStatistics::from_data(&data, 3, 3, &lopsided).unwrap().augmented(&[Symmetry::Rotate90], &variants).is_err());
+    //a ring of 4 has directions but nothing to turn
+    let mut ring = crate::topology::GraphTopology::new(4);
+    let next = ring.add_direction("next", "previous").unwrap();
+    for i in 0..4{
+        ring.connect(i, next, (i+1)%4).unwrap();
+    }
+    let mut sample = Grid::new_graph(ring);
+    for x in 0..4{
+        *sample.get_sqmut(x, 0) = (x%2) as TileType;
+    }
+    let stats = SampleStatistics::from_grid(&sample, &sample.get_layout().neighborhood());
+    assert!(matches!(stats.augmented(&[Symmetry::Rotate180], &variants), Err(Error::InvalidNeighborhood(_))));
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use serde_derive::{Deserialize, Serialize};
+use crate::{images::ByteImage, tile_set::TileType};
+
+//the 8 ways to turn or mirror a square. rotations go clockwise as the grid is drawn, with y pointing down
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Symmetry{
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    //left and right swap
+    FlipHorizontal,
+    //top and bottom swap
+    FlipVertical,
+    //mirrored across the line from the top left to the bottom right corner
+    FlipDiagonal,
+    //mirrored across the line from the top right to the bottom left corner
+    FlipAntiDiagonal,
+}
+
+impl Symmetry{
+    pub const ROTATIONS:[Symmetry; 4] = [Symmetry::Identity, Symmetry::Rotate90, Symmetry::Rotate180, Symmetry::Rotate270];
+    pub const ALL:[Symmetry; 8] = [Symmetry::Identity, Symmetry::Rotate90, Symmetry::Rotate180, Symmetry::Rotate270, Symmetry::FlipHorizontal, Symmetry::FlipVertical, Symmetry::FlipDiagonal, Symmetry::FlipAntiDiagonal];
+
+    //where an offset ends up, so a neighbor in direction offset ends up in direction apply(offset)
+    pub fn apply(&self, offset:(isize, isize))->(isize, isize){
+        let (dx, dy) = offset;
+        match self{
+            Symmetry::Identity=> (dx, dy),
+            Symmetry::Rotate90=> (-dy, dx),
+            Symmetry::Rotate180=> (-dx, -dy),
+            Symmetry::Rotate270=> (dy, -dx),
+            Symmetry::FlipHorizontal=> (-dx, dy),
+            Symmetry::FlipVertical=> (dx, -dy),
+            Symmetry::FlipDiagonal=> (dy, dx),
+            Symmetry::FlipAntiDiagonal=> (-dy, -dx),
+        }
+    }
+
+    //true if width and height trade places
+    pub fn swaps_axes(&self)->bool{
+        matches!(self, Symmetry::Rotate90|Symmetry::Rotate270|Symmetry::FlipDiagonal|Symmetry::FlipAntiDiagonal)
+    }
+
+    //the image turned or mirrored the same way, for making the variants of a tile
+    pub fn apply_to_image(&self, image:&ByteImage)->ByteImage{
+        let (width, height) = (image.get_width(), image.get_height());
+        let (out_width, out_height) = if self.swaps_axes(){(height, width)} else{(width, height)};
+        let mut out = ByteImage::new_from_colors(image.get_data(), out_height, out_width);
+        for y in 0..height{
+            for x in 0..width{
+                //doubled so the center of the image sits on a whole number
+                let (cx, cy) = self.apply((2*x as isize-(width as isize-1), 2*y as isize-(height as isize-1)));
+                let ox = ((cx+out_width as isize-1)/2) as usize;
+                let oy = ((cy+out_height as isize-1)/2) as usize;
+                out[oy][ox] = image[y][x];
+            }
+        }
+        out
+    }
+}
+
+//which tile a tile turns into under each symmetry. a tile that isn't listed for a symmetry looks the same after it,
+//like grass, while something like a road corner has to say which corner it becomes
+#[derive(Clone, Debug, Default)]
+pub struct TileVariants{
+    map:HashMap<(TileType, Symmetry), TileType>,
+}
+
+impl TileVariants{
+    pub fn new()->Self{
+        Self::default()
+    }
+
+    pub fn set(&mut self, tile:TileType, symmetry:Symmetry, variant:TileType){
+        self.map.insert((tile, symmetry), variant);
+    }
+
+    //tiles[i] turned 90 degrees clockwise is tiles[i+1], around and around
+    pub fn set_rotations(&mut self, tiles:[TileType; 4]){
+        for i in 0..4{
+            for (k, rotation) in Symmetry::ROTATIONS.iter().enumerate().skip(1){
+                self.set(tiles[i], *rotation, tiles[(i+k)%4]);
+            }
+        }
+    }
+
+    pub fn get(&self, tile:TileType, symmetry:Symmetry)->TileType{
+        if symmetry == Symmetry::Identity{
+            return tile;
+        }
+        *self.map.get(&(tile, symmetry)).unwrap_or(&tile)
+    }
+}
+
+#[test]
+fn test_symmetry(){
+    use crate::images::Color;
+    //every symmetry keeps the 8 around as the 8 around
+    for s in Symmetry::ALL{
+        let mut turned:Vec<(isize, isize)> = crate::utils::OFFSETS.iter().map(|o| s.apply(*o)).collect();
+        turned.sort();
+        let mut offsets = crate::utils::OFFSETS.to_vec();
+        offsets.sort();
+        assert_eq!(turned, offsets);
+    }
+    assert_eq!(Symmetry::Rotate90.apply((1, 0)), (0, 1));
+    assert_eq!(Symmetry::FlipDiagonal.apply((1, 0)), (0, 1));
+    //a 3 wide, 2 tall image with one red pixel in the top right corner
+    let (w, r) = (Color::WHITE, Color::RED);
+    let image = ByteImage::new_from_colors(&[w, w, r, w, w, w], 2, 3);
+    let turned = Symmetry::Rotate90.apply_to_image(&image);
+    assert_eq!((turned.get_width(), turned.get_height()), (2, 3));
+    assert_eq!(turned[2][1], r);
+    assert_eq!(Symmetry::FlipHorizontal.apply_to_image(&image)[0][0], r);
+    //the top right corner is on the anti diagonal so it stays put
+    assert_eq!(Symmetry::FlipAntiDiagonal.apply_to_image(&image)[0][1], r);
+    let mut variants = TileVariants::new();
+    variants.set_rotations([1, 2, 3, 4]);
+    assert_eq!(variants.get(4, Symmetry::Rotate180), 2);
+    assert_eq!(variants.get(0, Symmetry::Rotate90), 0);
+}
@@ -2,7 +2,7 @@ pub type TileType = i32;
 use raylib::{color, math::Vector2};
 use serde_derive::Serialize;
 
-use crate::{images::ByteImage, utils};
+use crate::{constraints::ConstraintSolver, error::Error, images::ByteImage, neighborhood::Neighborhood, symmetry::{Symmetry, TileVariants}, utils};
 use std::{collections::{HashMap, HashSet}, f32::consts::PI};
 #[derive(Clone, Serialize)]
 pub struct TileSet{
@@ -10,6 +10,95 @@ pub struct TileSet{
     pub tiles:HashMap<TileType, ByteImage>,
 }
 
+impl TileSet{
+    pub fn get_tile(&self, tile:TileType)->Result<&ByteImage, Error>{
+        self.tiles.get(&tile).ok_or(Error::TileOutOfRange(tile))
+    }
+}
+
+//a hand drawn example map cut into tiles, data says which tile is where row by row
+#[derive(Clone)]
+pub struct TiledSample{
+    pub tiles:TileSet,
+    pub data:Vec<TileType>,
+    pub height:usize,
+    pub width:usize,
+}
+
+//average difference of two images of the same size, per pixel and channel
+fn tile_difference(a:&ByteImage, b:&ByteImage)->f64{
+    let mut total = 0.0;
+    for (ca, cb) in a.get_data().iter().zip(b.get_data()){
+        total += (ca.r as f64-cb.r as f64).abs()+(ca.g as f64-cb.g as f64).abs()+(ca.b as f64-cb.b as f64).abs()+(ca.a as f64-cb.a as f64).abs();
+    }
+    total/(a.get_data().len()*4) as f64
+}
+
+impl TiledSample{
+    //cuts sample into tile_size squares, tiles no more than tolerance apart on average per channel (0 to 255)
+    //count as the same and are drawn like the first of them that was found
+    pub fn from_image(sample:&ByteImage, tile_size:usize, tolerance:f64)->Result<Self, Error>{
+        if tile_size == 0 || !sample.get_width().is_multiple_of(tile_size) || !sample.get_height().is_multiple_of(tile_size){
+            return Err(Error::InvalidSample(format!("{}x{} doesn't split into {tile_size}x{tile_size} tiles", sample.get_width(), sample.get_height())));
+        }
+        let height = sample.get_height()/tile_size;
+        let width = sample.get_width()/tile_size;
+        let mut found:Vec<ByteImage> = Vec::new();
+        let mut data = Vec::with_capacity(height*width);
+        for y in 0..height{
+            for x in 0..width{
+                let tile = sample.sub_image(x*tile_size, y*tile_size, (x+1)*tile_size, (y+1)*tile_size);
+                let idx = match found.iter().position(|f| tile_difference(f, &tile)<= tolerance){
+                    Some(idx)=> idx,
+                    None=>{
+                        found.push(tile);
+                        found.len()-1
+                    }
+                };
+                data.push(idx as TileType);
+            }
+        }
+        let tiles = found.into_iter().enumerate().map(|(i, t)| (i as TileType, t)).collect();
+        Ok(Self{tiles:TileSet{tile_size, tiles}, data, height, width})
+    }
+
+    //a solver for a grid the size of the sample that learned its borders from it, use with_size for other sizes
+    pub fn solver(&self)->Result<ConstraintSolver, Error>{
+        ConstraintSolver::new_from_data(&self.data, self.height, self.width)
+    }
+
+    //turns and mirrors every tile by each of symmetries, adding the ones that don't look like any tile yet (within tolerance)
+    //to the tile set, and says which tile each one became
+    pub fn add_variants(&mut self, symmetries:&[Symmetry], tolerance:f64)->TileVariants{
+        let mut variants = TileVariants::new();
+        let mut ids:Vec<TileType> = self.tiles.tiles.keys().copied().collect();
+        ids.sort();
+        for tile in ids{
+            for s in symmetries{
+                let image = s.apply_to_image(&self.tiles.tiles[&tile]);
+                let mut known:Vec<(&TileType, &ByteImage)> = self.tiles.tiles.iter().collect();
+                known.sort_by_key(|i| *i.0);
+                let variant = match known.iter().find(|(_, t)| tile_difference(t, &image)<= tolerance){
+                    Some((id, _))=> **id,
+                    None=>{
+                        let id = self.tiles.tiles.keys().max().map(|i| i+1).unwrap_or(0);
+                        self.tiles.tiles.insert(id, image);
+                        id
+                    }
+                };
+                variants.set(tile, *s, variant);
+            }
+        }
+        variants
+    }
+
+    //like solver but also learns from the sample turned and mirrored by each of symmetries, new tile variants get added to the tile set
+    pub fn solver_with_symmetry(&mut self, symmetries:&[Symmetry], tolerance:f64)->Result<ConstraintSolver, Error>{
+        let variants = self.add_variants(symmetries, tolerance);
+        ConstraintSolver::new_from_data_with_symmetry(&self.data, self.height, self.width, Neighborhood::moore(), symmetries, &variants)
+    }
+}
+
 #[allow(unused)]
 #[derive(Clone)]
 struct StreetTile{
@@ -108,4 +197,37 @@ pub fn make_city_tile_set()->(TileSet, HashMap<TileType,Vec<HashSet<TileType>>>)
     let constraints = calc_street_tile_bounds(&tiles);
     let tile_set = TileSet{tile_size, tiles:tile_map};
     (tile_set, constraints)
-}
\ No newline at end of file
+}
+#[test]
+fn test_tiled_sample(){
+    use raylib::color::Color;
+    use crate::constraints::SelectionStrategy;
+    //3x3 tiles of 2x2 pixels, a nearly white tile in the middle
+    let mut sample = ByteImage::new_from_color(Color::WHITE, 6, 6);
+    for y in 0..6{
+        for x in 0..6{
+            if (x/2+y/2)%2 == 1{
+                sample[y][x] = Color::BLUE;
+            }
+        }
+    }
+    sample[2][2] = Color::new(250, 252, 255, 255);
+    let tiled = TiledSample::from_image(&sample, 2, 4.0).unwrap();
+    assert_eq!(tiled.tiles.tiles.len(), 2);
+    assert_eq!(tiled.data, vec![0, 1, 0, 1, 0, 1, 0, 1, 0]);
+    assert_eq!(TiledSample::from_image(&sample, 2, 0.0).unwrap().tiles.tiles.len(), 3);
+    assert!(TiledSample::from_image(&sample, 4, 0.0).is_err());
+    let mut solve = tiled.solver().unwrap().with_size(5, 5).unwrap();
+    solve.set_seed(1);
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    let out = solve.get_grid().draw_as_byte_image(&tiled.tiles).unwrap();
+    assert_eq!((out.get_width(), out.get_height()), (10, 10));
+    //plain tiles look the same however they're turned, a tile with a mark in one corner has 4 new variants
+    let mut turned = tiled.clone();
+    assert!(turned.solver_with_symmetry(&Symmetry::ALL, 4.0).is_ok());
+    assert_eq!(turned.tiles.tiles.len(), 2);
+    let mut marked = TiledSample::from_image(&sample, 2, 0.0).unwrap();
+    let variants = marked.add_variants(&Symmetry::ROTATIONS, 0.0);
+    assert_eq!(marked.tiles.tiles.len(), 6);
+    assert_eq!(variants.get(2, Symmetry::Rotate180), 4);
+}
@@ -0,0 +1,242 @@
+use serde_derive::{Deserialize, Serialize};
+use crate::{constraints::{Boundary, Grid}, error::Error, neighborhood::Neighborhood, utils};
+
+//how cells are laid out: how many there are, which cells each one touches and in which direction.
+//directions come in pairs, if b is in direction dir of a then a is in direction inverse(dir) of b
+pub trait Topology: std::fmt::Debug{
+    fn cell_count(&self)->usize;
+
+    fn direction_count(&self)->usize;
+
+    //every edge leaving cell as (direction, other cell). a cell can have any number of neighbors in a direction, or none
+    fn neighbors(&self, cell:usize)->Vec<(usize, usize)>;
+
+    fn inverse(&self, dir:usize)->usize;
+
+    //a name for the direction, for printing
+    fn direction_label(&self, dir:usize)->String{
+        format!("direction {dir}")
+    }
+}
+
+//a flat grid like the one ConstraintSolver works on, cells are numbered row by row and directions are the offsets of neighborhood
+#[derive(Clone, Debug, PartialEq)]
+pub struct SquareTopology{
+    height:usize,
+    width:usize,
+    horizontal:Boundary,
+    vertical:Boundary,
+    neighborhood:Neighborhood,
+}
+
+impl SquareTopology{
+    pub fn new(height:usize, width:usize, neighborhood:Neighborhood)->Self{
+        Self{height, width, horizontal:Boundary::Open, vertical:Boundary::Open, neighborhood}
+    }
+
+    //the size and boundaries of grid
+    pub fn of_grid(grid:&Grid, neighborhood:Neighborhood)->Self{
+        let (horizontal, vertical) = grid.get_boundary();
+        Self{height:grid.get_height(), width:grid.get_width(), horizontal, vertical, neighborhood}
+    }
+
+    pub fn set_boundary(&mut self, horizontal:Boundary, vertical:Boundary){
+        self.horizontal = horizontal;
+        self.vertical = vertical;
+    }
+}
+
+impl Topology for SquareTopology{
+    fn cell_count(&self)->usize{
+        self.height*self.width
+    }
+
+    fn direction_count(&self)->usize{
+        self.neighborhood.len()
+    }
+
+    fn neighbors(&self, cell:usize)->Vec<(usize, usize)>{
+        let (x, y) = (cell%self.width, cell/self.width);
+        self.neighborhood.offsets().iter().enumerate().filter_map(|(dir, (dx, dy))|{
+            let nx = Grid::step(x, *dx, self.width, self.horizontal)?;
+            let ny = Grid::step(y, *dy, self.height, self.vertical)?;
+            Some((dir, ny*self.width+nx))
+        }).collect()
+    }
+
+    fn inverse(&self, dir:usize)->usize{
+        self.neighborhood.opposite(dir)
+    }
+
+    fn direction_label(&self, dir:usize)->String{
+        let offset = self.neighborhood.offsets()[dir];
+        match utils::OFFSETS.iter().position(|o| *o == offset){
+            Some(i)=> utils::OFFSET_NAMES[i].to_string(),
+            None=> format!("{offset:?}"),
+        }
+    }
+}
+
+//any graph, like rooms joined by corridors or the regions of a voronoi map. directions are named and
+//each has an inverse, which can be itself for links that look the same from both ends
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GraphTopology{
+    labels:Vec<String>,
+    inverses:Vec<usize>,
+    //edges[cell] is every (direction, other cell) leaving cell
+    edges:Vec<Vec<(usize, usize)>>,
+}
+
+impl GraphTopology{
+    pub fn new(cell_count:usize)->Self{
+        Self{labels:Vec::new(), inverses:Vec::new(), edges:vec![Vec::new(); cell_count]}
+    }
+
+    pub fn add_cell(&mut self)->usize{
+        self.edges.push(Vec::new());
+        self.edges.len()-1
+    }
+
+    //adds a direction and its inverse, pass the same label twice for one that is its own inverse.
+    //returns the index of label, directions that already exist are reused
+    pub fn add_direction(&mut self, label:&str, inverse:&str)->Result<usize, Error>{
+        if let (Some(a), Some(b)) = (self.direction(label), self.direction(inverse)){
+            if self.inverses[a] == b{
+                return Ok(a);
+            }
+        }
+        if self.direction(label).is_some() || self.direction(inverse).is_some(){
+            return Err(Error::InvalidNeighborhood(format!("{label} or {inverse} already has another inverse")));
+        }
+        let a = self.labels.len();
+        self.labels.push(label.to_string());
+        if label == inverse{
+            self.inverses.push(a);
+            return Ok(a);
+        }
+        self.labels.push(inverse.to_string());
+        self.inverses.push(a+1);
+        self.inverses.push(a);
+        Ok(a)
+    }
+
+    pub fn direction(&self, label:&str)->Option<usize>{
+        self.labels.iter().position(|l| l == label)
+    }
+
+    //puts b in direction dir of a, and a in the inverse direction of b
+    pub fn connect(&mut self, a:usize, dir:usize, b:usize)->Result<(), Error>{
+        for cell in [a, b]{
+            if cell>= self.edges.len(){
+                return Err(Error::CellOutOfBounds(cell));
+            }
+        }
+        if dir>= self.labels.len(){
+            return Err(Error::InvalidNeighborhood(format!("there is no direction {dir}")));
+        }
+        self.edges[a].push((dir, b));
+        self.edges[b].push((self.inverses[dir], a));
+        Ok(())
+    }
+}
+
+impl Topology for GraphTopology{
+    fn cell_count(&self)->usize{
+        self.edges.len()
+    }
+
+    fn direction_count(&self)->usize{
+        self.labels.len()
+    }
+
+    fn neighbors(&self, cell:usize)->Vec<(usize, usize)>{
+        self.edges[cell].clone()
+    }
+
+    fn inverse(&self, dir:usize)->usize{
+        self.inverses[dir]
+    }
+
+    fn direction_label(&self, dir:usize)->String{
+        self.labels[dir].clone()
+    }
+}
+
+#[test]
+fn test_topologies(){
+    use std::{collections::{HashMap, HashSet}, sync::Arc};
+    use crate::{constraints::{ConstraintSolver, GridConstraint, Layout, SelectionStrategy}, hex::HexTopology, voxel::{VoxelGrid, VoxelNeighborhood}};
+    //every edge of every topology has to come back the other way
+    fn check_inverses(t:&impl Topology){
+        for cell in 0..t.cell_count(){
+            for (dir, other) in t.neighbors(cell){
+                assert!(t.neighbors(other).contains(&(t.inverse(dir), cell)), "{} from {cell} has no way back", t.direction_label(dir));
+            }
+        }
+    }
+    let mut square = SquareTopology::new(3, 4, Neighborhood::moore());
+    check_inverses(&square);
+    assert_eq!(square.neighbors(0).len(), 3);
+    square.set_boundary(Boundary::Wrap, Boundary::Open);
+    check_inverses(&square);
+    assert_eq!(square.neighbors(0).len(), 5);
+    assert_eq!(square.direction_label(0), "top left");
+    let mut wrapped = crate::constraints::ConstraintSolver::new_from_data_with_neighborhood(&[0, 1, 1, 0], 2, 2, Neighborhood::von_neumann()).unwrap().with_size(3, 4).unwrap();
+    wrapped.set_boundary(Boundary::Wrap, Boundary::Wrap).unwrap();
+    assert_eq!(wrapped.topology().neighbors(0), vec![(0, 8), (1, 3), (2, 1), (3, 4)]);
+    check_inverses(&HexTopology::new(4, 5, Neighborhood::hex()));
+    check_inverses(&VoxelGrid::new(3, 2, 2).topology(VoxelNeighborhood::TwentySix));
+    //a hub with three rooms off it. a room or a stairwell only ever opens onto a hall, a hall onto rooms and stairwells
+    let mut dungeon = GraphTopology::new(4);
+    let door = dungeon.add_direction("door", "door").unwrap();
+    assert!(dungeon.add_direction("door", "wall").is_err());
+    for room in 1..4{
+        dungeon.connect(0, door, room).unwrap();
+    }
+    assert!(dungeon.connect(0, door, 9).is_err());
+    check_inverses(&dungeon);
+    let (hall, room, stairs) = (0, 1, 2);
+    let borders = HashMap::from([(hall, vec![HashSet::from([room, stairs])]), (room, vec![HashSet::from([hall])]), (stairs, vec![HashSet::from([hall])])]);
+    let layout = Layout::Graph(Arc::new(dungeon));
+    let constraints = vec![GridConstraint::new_from_borders_with_neighborhood(borders, layout.neighborhood()).unwrap()];
+    let mut solve = ConstraintSolver::new_with_layout(layout, 1, 4, Arc::new([hall, room, stairs]), constraints).unwrap();
+    solve.set_seed(1);
+    solve.pin(&[(2, 0, stairs)]).unwrap();
+    assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+    assert_eq!(*solve.get_grid().get_sq(0, 0), hall);
+    assert!(solve.get_grid().get_data()[1..].iter().all(|v| *v != hall));
+}
+
+#[test]
+fn test_graph_solver(){
+    use crate::{constraints::{ConstraintRegistry, ConstraintSolver, SelectionStrategy}, statistics::SampleStatistics};
+    //cells in a ring, each with a next and a previous neighbor. 0 and 1 have to take turns, which an odd ring can't do
+    let ring = |n:usize|{
+        let mut out = GraphTopology::new(n);
+        let next = out.add_direction("next", "previous").unwrap();
+        for i in 0..n{
+            out.connect(i, next, (i+1)%n).unwrap();
+        }
+        Grid::new_graph(out)
+    };
+    let mut sample = ring(6);
+    for x in 0..6{
+        *sample.get_sqmut(x, 0) = (x%2) as i32;
+    }
+    let directions = sample.get_layout().neighborhood();
+    let stats = SampleStatistics::from_grid(&sample, &directions);
+    assert_eq!(stats.weights(), vec![(0, 3.0), (1, 3.0)]);
+    let mut odd = ConstraintSolver::new_from_sample(&sample, directions.clone()).unwrap().with_layout(ring(5).get_layout().clone(), 1, 5).unwrap();
+    assert!(matches!(odd.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom), Err(Error::Unsatisfiable)));
+    let mut even = ConstraintSolver::new_from_sample(&sample, directions).unwrap();
+    even.pin(&[(3, 0, 1)]).unwrap();
+    //one pin decides the whole ring
+    assert_eq!(even.undefined_count(), 0);
+    assert_eq!(even.get_grid().get_data(), &[0, 1, 0, 1, 0, 1]);
+    assert!(matches!(even.pin(&[(2, 0, 1)]), Err(Error::Contradiction { x:2, y:0 })));
+    assert!(matches!(even.pin(&[(6, 0, 1)]), Err(Error::OutOfBounds { x:6, y:0 })));
+    //the graph is shared between grids but still saved whole
+    let loaded = ConstraintSolver::load_setup(&even.save_setup().unwrap(), &ConstraintRegistry::new()).unwrap();
+    assert_eq!(loaded.get_grid().get_layout(), even.get_grid().get_layout());
+    assert_eq!(loaded.topology().neighbors(0), even.topology().neighbors(0));
+}
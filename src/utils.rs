@@ -1,7 +1,7 @@
 
 
 
-use rand::{thread_rng, RngCore};
+use rand::{Rng, RngCore};
 use raylib::math::{Rectangle, Vector2};
 
 #[allow(unused)]
@@ -10,19 +10,45 @@ pub const OFFSETS:[(isize,isize);8] = [(-1,-1), (0,-1), (1,-1), (-1,0), (1,0), (
 pub const OFFSET_NAMES:[&str; 8] = ["top left", "top center", "top right", "middle left", "middle right", "bottom left", "bottom center", "bottom right"];
 pub const MINUS_INDICES:[usize; 8] = [7, 6, 5, 4, 3, 2,1,0];
 
-#[allow(unused)]
-pub fn slice_rand_select<T:Clone>(values:&[(T, f64)])->T{
-    let total = values.iter().map(|i| i.1).fold(0.0, |a:f64, b:f64| {a+b});
-    let rnd= (thread_rng().next_u32()%10000)  as f64 /10000.0;
-    let mut idx = 0;
-    let mut base =0.0;
-    while (values[idx].1+base)/total<rnd{
-        base += values[idx].1;
-        idx += 1;
+//picks a value with probability proportional to its weight, values with a weight of zero or less are never picked
+//returns None if nothing has a positive weight
+pub fn slice_rand_select<T:Clone>(values:&[(T, f64)], rng:&mut impl RngCore)->Option<T>{
+    let total:f64 = values.iter().map(|i| i.1).filter(|i| *i>0.0).sum();
+    if total<= 0.0{
+        return None;
+    }
+    let mut rnd = rng.gen::<f64>()*total;
+    let mut last = None;
+    for (v, w) in values{
+        if *w<= 0.0{
+            continue;
+        }
+        if rnd<*w{
+            return Some(v.clone());
+        }
+        rnd -= w;
+        last = Some(v);
     }
-    values[idx].0.clone()
+    //only reachable through rounding
+    last.cloned()
 }
 
+//shannon entropy (in nats) of a set of weights, non positive weights are ignored
+pub fn shannon_entropy(weights:&[f64])->f64{
+    let total:f64 = weights.iter().filter(|i| **i>0.0).sum();
+    if total<= 0.0{
+        return 0.0;
+    }
+    let mut out = 0.0;
+    for w in weights{
+        if *w<= 0.0{
+            continue;
+        }
+        let p = w/total;
+        out -= p*p.ln();
+    }
+    out
+}
 
 #[allow(unused)]
 pub fn slice_intersection<T:Clone+PartialEq>(a:&[T], b:&[T], is_equal:&impl Fn (&T,&T)->bool)->Vec<T>{
@@ -80,7 +106,8 @@ pub fn rect_center(rect:&Rectangle)->Vector2{
 
 #[test]
 fn test_rect_scale(){
-    let mut r = thread_rng();
+    use rand::SeedableRng;
+    let mut r = rand::rngs::StdRng::seed_from_u64(11);
     let mut r2 = r.clone();
     let mut rfloat = ||{
         (r.next_u64()%10000) as f32 /5000.0-1.0
@@ -98,6 +125,30 @@ fn test_rect_scale(){
     }
 }
 
+#[test]
+fn test_shannon_entropy(){
+    assert!(shannon_entropy(&[1.0]).abs()<1e-12);
+    assert!((shannon_entropy(&[1.0, 1.0, 1.0, 1.0])-(4.0f64).ln()).abs()<1e-12);
+    assert!((shannon_entropy(&[2.0, 2.0, 0.0])-(2.0f64).ln()).abs()<1e-12);
+    assert!(shannon_entropy(&[3.0, 1.0])<shannon_entropy(&[1.0, 1.0]));
+}
+
+#[test]
+fn test_slice_rand_select(){
+    use rand::SeedableRng;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    assert_eq!(slice_rand_select(&[(0, 0.0), (1, -2.0)], &mut rng), None);
+    let values = [(0, 1.0), (1, 3.0), (2, 0.0), (3, -1.0)];
+    let mut counts = [0; 4];
+    for _ in 0..20000{
+        counts[slice_rand_select(&values, &mut rng).unwrap()] += 1;
+    }
+    assert_eq!(counts[2], 0);
+    assert_eq!(counts[3], 0);
+    let ratio = counts[1] as f64/counts[0] as f64;
+    assert!((ratio-3.0).abs()<0.2);
+}
+
 #[allow(unused)]
 pub fn rectangle_centered(x:f32, y:f32, half_width:f32, half_height:f32)->Rectangle{
     Rectangle{x:x-half_width, y:y-half_height, width:half_width*2.0, height:half_height*2.0
@@ -0,0 +1,296 @@
+use serde_derive::{Deserialize, Serialize};
+use crate::{constraints::{Grid, Layout}, error::Error, images::ByteImage, tile_set::{TileSet, TileType}, topology::Topology};
+
+//which voxels a border constraint looks at: the 6 sharing a face, or all 26 around.
+//offsets are (dx, dy, dz) with z going up through the layers, listed so the opposite of direction i is len-1-i
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VoxelNeighborhood{
+    #[default]
+    Six,
+    TwentySix,
+}
+
+impl VoxelNeighborhood{
+    pub fn offsets(&self)->Vec<(isize, isize, isize)>{
+        match self{
+            Self::Six=> vec![(0, 0, -1), (0, -1, 0), (-1, 0, 0), (1, 0, 0), (0, 1, 0), (0, 0, 1)],
+            Self::TwentySix=>{
+                let mut out = Vec::with_capacity(26);
+                for dz in -1..=1{
+                    for dy in -1..=1{
+                        for dx in -1..=1{
+                            if (dx, dy, dz) != (0, 0, 0){
+                                out.push((dx, dy, dz));
+                            }
+                        }
+                    }
+                }
+                out
+            }
+        }
+    }
+
+    pub fn len(&self)->usize{
+        match self{
+            Self::Six=> 6,
+            Self::TwentySix=> 26,
+        }
+    }
+
+    pub fn is_empty(&self)->bool{
+        false
+    }
+
+    pub fn opposites(&self)->Vec<usize>{
+        (0..self.len()).rev().collect()
+    }
+
+    pub fn index_of(&self, offset:(isize, isize, isize))->Option<usize>{
+        self.offsets().iter().position(|o| *o == offset)
+    }
+}
+
+//a stack of layers of width x height cells, layer 0 at the bottom. -1 is a voxel with no value yet
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VoxelGrid{
+    values:Box<[TileType]>,
+    layers:usize,
+    height:usize,
+    width:usize,
+}
+
+impl VoxelGrid{
+    pub fn new(layers:usize, height:usize, width:usize)->Self{
+        Self{values:vec![-1; layers*height*width].into(), layers, height, width}
+    }
+
+    //data is layer by layer, each layer row by row
+    pub fn new_from_data(data:&[TileType], layers:usize, height:usize, width:usize)->Result<Self, Error>{
+        if data.len() != layers*height*width{
+            return Err(Error::SizeMismatch { expected: layers*height*width, found: data.len() });
+        }
+        Ok(Self{values:data.into(), layers, height, width})
+    }
+
+    pub fn get_layers(&self)->usize{
+        self.layers
+    }
+
+    pub fn get_height(&self)->usize{
+        self.height
+    }
+
+    pub fn get_width(&self)->usize{
+        self.width
+    }
+
+    pub fn get_data(&self)->&[TileType]{
+        &self.values
+    }
+
+    pub fn index(&self, x:usize, y:usize, z:usize)->usize{
+        (z*self.height+y)*self.width+x
+    }
+
+    pub fn position(&self, idx:usize)->(usize, usize, usize){
+        (idx%self.width, (idx/self.width)%self.height, idx/(self.width*self.height))
+    }
+
+    pub fn contains(&self, x:usize, y:usize, z:usize)->bool{
+        x<self.width && y<self.height && z<self.layers
+    }
+
+    pub fn get(&self, x:usize, y:usize, z:usize)->&TileType{
+        assert!(self.contains(x, y, z));
+        &self.values[self.index(x, y, z)]
+    }
+
+    pub fn get_mut(&mut self, x:usize, y:usize, z:usize)->&mut TileType{
+        assert!(self.contains(x, y, z));
+        let idx = self.index(x, y, z);
+        &mut self.values[idx]
+    }
+
+    //the voxel dx, dy, dz away from (x, y, z), None if that's outside the grid
+    pub fn neighbor(&self, x:usize, y:usize, z:usize, dx:isize, dy:isize, dz:isize)->Option<(usize, usize, usize)>{
+        let (nx, ny, nz) = (x as isize+dx, y as isize+dy, z as isize+dz);
+        if nx<0 || ny<0 || nz<0{
+            return None;
+        }
+        let (nx, ny, nz) = (nx as usize, ny as usize, nz as usize);
+        self.contains(nx, ny, nz).then_some((nx, ny, nz))
+    }
+
+    //the grid's shape with neighborhood as its directions
+    pub fn topology(&self, neighborhood:VoxelNeighborhood)->VoxelTopology{
+        VoxelTopology::new(self.layers, self.height, self.width, neighborhood)
+    }
+
+    //the voxels as a Grid laid out as Layout::Voxel, to solve or learn from with ConstraintSolver. the grid has a row
+    //for every row of every layer, see grid_position
+    pub fn to_grid(&self, neighborhood:VoxelNeighborhood)->Result<Grid, Error>{
+        let mut out = Grid::new_with_layout(self.layers*self.height, self.width, Layout::Voxel{layers:self.layers, neighborhood})?;
+        for (i, v) in self.values.iter().enumerate(){
+            let (x, y) = out.position(i);
+            *out.get_sqmut(x, y) = *v;
+        }
+        Ok(out)
+    }
+
+    //the voxels of a grid made with to_grid, a grid laid out any other way is a single layer.
+    //fails if the grid's height isn't a whole number of layers
+    pub fn from_grid(grid:&Grid)->Result<Self, Error>{
+        let layers = match grid.get_layout(){
+            Layout::Voxel{layers, ..}=> *layers,
+            _=> 1,
+        };
+        let height = grid.get_height().checked_div(layers).unwrap_or(0);
+        Self::new_from_data(grid.get_data(), layers, height, grid.get_width())
+    }
+
+    //where (x, y, z) is in the grid made by to_grid
+    pub fn grid_position(&self, x:usize, y:usize, z:usize)->(usize, usize){
+        (x, z*self.height+y)
+    }
+
+    //one layer as a flat grid, to draw or to look at with the 2d tools
+    pub fn layer(&self, z:usize)->Grid{
+        let mut out = Grid::new(self.height, self.width);
+        for y in 0..self.height{
+            for x in 0..self.width{
+                *out.get_sqmut(x, y) = *self.get(x, y, z);
+            }
+        }
+        out
+    }
+
+    //every layer drawn with tileset, bottom first
+    pub fn export_layers(&self, tileset:&TileSet)->Result<Vec<ByteImage>, Error>{
+        (0..self.layers).map(|z| self.layer(z).draw_as_byte_image(tileset)).collect()
+    }
+
+    pub fn save(&self)->Result<String, Error>{
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn load(data:&str)->Result<Self, Error>{
+        let out:Self = serde_json::from_str(data)?;
+        if out.values.len() != out.layers*out.height*out.width{
+            return Err(Error::SizeMismatch { expected: out.layers*out.height*out.width, found: out.values.len() });
+        }
+        Ok(out)
+    }
+
+    pub fn write_to_file(&self, file_name:&str)->Result<(), Error>{
+        std::fs::write(file_name, self.save()?)?;
+        Ok(())
+    }
+
+    pub fn load_from_file(file_name:&str)->Result<Self, Error>{
+        Self::load(&std::fs::read_to_string(file_name)?)
+    }
+}
+
+//the cells of a voxel grid numbered like VoxelGrid::index, with the directions of neighborhood
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoxelTopology{
+    layers:usize,
+    height:usize,
+    width:usize,
+    neighborhood:VoxelNeighborhood,
+}
+
+impl VoxelTopology{
+    pub fn new(layers:usize, height:usize, width:usize, neighborhood:VoxelNeighborhood)->Self{
+        Self{layers, height, width, neighborhood}
+    }
+}
+
+impl Topology for VoxelTopology{
+    fn cell_count(&self)->usize{
+        self.layers*self.height*self.width
+    }
+
+    fn direction_count(&self)->usize{
+        self.neighborhood.len()
+    }
+
+    fn neighbors(&self, cell:usize)->Vec<(usize, usize)>{
+        let (x, y, z) = (cell%self.width, (cell/self.width)%self.height, cell/(self.width*self.height));
+        self.neighborhood.offsets().iter().enumerate().filter_map(|(dir, (dx, dy, dz))|{
+            let (nx, ny, nz) = (x as isize+dx, y as isize+dy, z as isize+dz);
+            let inside = (0..self.width as isize).contains(&nx) && (0..self.height as isize).contains(&ny) && (0..self.layers as isize).contains(&nz);
+            inside.then(|| (dir, (nz as usize*self.height+ny as usize)*self.width+nx as usize))
+        }).collect()
+    }
+
+    fn inverse(&self, dir:usize)->usize{
+        self.neighborhood.len()-1-dir
+    }
+
+    fn direction_label(&self, dir:usize)->String{
+        format!("{:?}", self.neighborhood.offsets()[dir])
+    }
+}
+
+#[test]
+fn test_voxel_solver(){
+    use crate::{constraints::{ConstraintRegistry, ConstraintSolver, SelectionStrategy}, global::TileCount};
+    //a two storey house: 1 is floor, 2 is air, and floors only ever sit on top of air or the ground
+    let mut sample = VoxelGrid::new(4, 2, 2);
+    for z in 0..4{
+        for y in 0..2{
+            for x in 0..2{
+                *sample.get_mut(x, y, z) = if z%2 == 0{1} else{2};
+            }
+        }
+    }
+    let house = VoxelGrid::new(6, 3, 3);
+    for neighborhood in [VoxelNeighborhood::Six, VoxelNeighborhood::TwentySix]{
+        let offsets = neighborhood.offsets();
+        for (i, o) in offsets.iter().enumerate(){
+            assert_eq!(offsets[neighborhood.opposites()[i]], (-o.0, -o.1, -o.2));
+        }
+        let learned = sample.to_grid(neighborhood).unwrap();
+        assert_eq!(VoxelGrid::from_grid(&learned).unwrap(), sample);
+        let mut solve = ConstraintSolver::new_from_sample(&learned, learned.get_layout().neighborhood()).unwrap().with_layout(Layout::Voxel{layers:6, neighborhood}, 18, 3).unwrap();
+        solve.set_seed(5);
+        assert!(solve.collapse_fully_backtracking(&SelectionStrategy::PurelyRandom).is_ok());
+        let grid = VoxelGrid::from_grid(solve.get_grid()).unwrap();
+        //every layer is all one thing and they take turns
+        for z in 0..6{
+            let layer = grid.layer(z);
+            assert!((0..9).all(|i| layer.get_sq(i%3, i/3) == grid.get(0, 0, z)));
+            if z>0{
+                assert_ne!(grid.get(0, 0, z), grid.get(0, 0, z-1));
+            }
+        }
+    }
+    let learned = sample.to_grid(VoxelNeighborhood::Six).unwrap();
+    let mut solve = ConstraintSolver::new_from_sample(&learned, learned.get_layout().neighborhood()).unwrap().with_layout(Layout::Voxel{layers:6, neighborhood:VoxelNeighborhood::Six}, 18, 3).unwrap();
+    let (x, y) = house.grid_position(1, 1, 0);
+    solve.pin(&[(x, y, 2)]).unwrap();
+    let (x, y) = house.grid_position(2, 0, 5);
+    assert_eq!(*solve.get_grid().get_sq(x, y), 1);
+    assert!(matches!(solve.pin(&[(3, 0, 1)]), Err(Error::OutOfBounds { x:3, y:0 })));
+    assert_eq!(solve.get_grid().get_data().iter().filter(|v| **v == 1).count(), 27);
+    //the pinned house has 27 floor voxels, so a limit of 26 can't hold
+    assert!(solve.add_global_constraint(TileCount::at_most(&[1], 26)).is_err());
+    let loaded = ConstraintSolver::load_setup(&solve.save_setup().unwrap(), &ConstraintRegistry::new()).unwrap();
+    assert_eq!(loaded.get_grid().get_layout(), solve.get_grid().get_layout());
+    let voxels = VoxelGrid::from_grid(loaded.get_grid()).unwrap();
+    assert_eq!(VoxelGrid::load(&voxels.save().unwrap()).unwrap(), VoxelGrid::from_grid(solve.get_grid()).unwrap());
+}
+
+#[test]
+fn test_voxel_layer_sizes(){
+    use crate::constraints::ConstraintSolver;
+    let neighborhood = VoxelNeighborhood::Six;
+    //17 rows don't split into 6 layers, so the last rows would be left out of the topology
+    assert!(matches!(Grid::new_with_layout(17, 3, Layout::Voxel{layers:6, neighborhood}), Err(Error::SizeMismatch { expected:18, found:17 })));
+    assert!(matches!(Grid::new_with_layout(6, 3, Layout::Voxel{layers:0, neighborhood}), Err(Error::SizeMismatch { expected:0, found:6 })));
+    let solve = ConstraintSolver::new_from_data(&[1, 2, 1, 2], 2, 2).unwrap();
+    assert!(matches!(solve.with_layout(Layout::Voxel{layers:6, neighborhood}, 17, 3), Err(Error::SizeMismatch { expected:18, found:17 })));
+    assert!(solve.with_layout(Layout::Voxel{layers:0, neighborhood}, 0, 3).is_err());
+    assert!(solve.with_layout(Layout::Voxel{layers:6, neighborhood}, 18, 3).is_ok());
+}